@@ -0,0 +1,426 @@
+use std::thread::{self, JoinHandle};
+
+use crate::compute::texture::{bake_normal_map, bake_texture, bake_texture_projected, NormalMap, Texture};
+use crate::compute::{bake, bake_projected, AccelStructure, Adaptive, BakeMode, BakeTarget, CancelToken, ComputeData, Distribution, Falloff, GroundPlane, Progress, Projection, RayDistance, Sequence};
+use crate::error::{Error, Result};
+use crate::geo::Vertex;
+
+/// Builder for baking AO of an indexed triangle mesh.
+///
+/// ```no_run
+/// use ao_baker::Baker;
+///
+/// # fn main() -> ao_baker::Result<()> {
+/// let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+/// let normals = [[0.0, 0.0, 1.0]; 3];
+/// let ao = Baker::new(&positions, &normals, &[0, 1, 2])
+///     .samples(256)
+///     .bake()?;
+/// assert_eq!(ao.len(), 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Baker {
+    verts: Vec<Vertex>,
+    indices: Vec<u32>,
+    settings: ComputeData,
+    /// whether `uvs` were set, texture bakes need them
+    has_uvs: bool,
+    /// high poly vertices and indices projected onto the mesh
+    high: Option<(Vec<Vertex>, Vec<u32>)>,
+    cage: Option<Vec<Vertex>>,
+    /// first problem with the given geometry, reported by every bake
+    problem: Option<String>,
+    cancel: CancelToken
+}
+
+/// Clones get their own cancel token so cancelling one bake leaves the others running
+impl Clone for Baker {
+    fn clone(&self) -> Self {
+        Baker {
+            verts: self.verts.clone(),
+            indices: self.indices.clone(),
+            settings: self.settings,
+            has_uvs: self.has_uvs,
+            high: self.high.clone(),
+            cage: self.cage.clone(),
+            problem: self.problem.clone(),
+            cancel: CancelToken::new()
+        }
+    }
+}
+
+impl Baker {
+    /// Creates baker from per-vertex positions and normals, `indices` form triangle list.
+    /// Bakes fail if `positions` and `normals` differ in length, if `indices` is not a triangle list
+    /// or if any index is out of range.
+    pub fn new(positions: &[[f32; 3]], normals: &[[f32; 3]], indices: &[u32]) -> Self {
+        let problem = if positions.len() != normals.len() {
+            Some("every position needs a normal")
+        } else if indices.len() % 3 != 0 {
+            Some("indices must form a triangle list")
+        } else if indices.iter().any(|&i| i as usize >= positions.len()) {
+            Some("index out of range")
+        } else {
+            None
+        };
+
+        let verts = positions.iter().zip(normals).map(|(&pos, &normal)| Vertex::new(pos, normal)).collect();
+
+        Baker {
+            verts,
+            indices: indices.to_vec(),
+            settings: ComputeData::default(),
+            has_uvs: false,
+            high: None,
+            cage: None,
+            problem: problem.map(str::to_string),
+            cancel: CancelToken::new()
+        }
+    }
+
+    /// Sets per-vertex texture coordinates used by `bake_texture`.
+    /// Bakes fail if `uvs` differ in length from positions.
+    pub fn uvs(mut self, uvs: &[[f32; 2]]) -> Self {
+        if uvs.len() != self.verts.len() {
+            self.report("every position needs a texture coordinate");
+        }
+        for (vert, &uv) in self.verts.iter_mut().zip(uvs) {
+            vert.uv = uv;
+        }
+        self.has_uvs = true;
+        self
+    }
+
+    /// Bakes AO of high poly mesh made of `positions` with `normals` and triangle list `indices` projected onto
+    /// this one. Rays search for its surface along normals from cage distance in front of every baked point.
+    /// Bakes fail if `positions` and `normals` differ in length, if `indices` is not a triangle list
+    /// or if any index is out of range.
+    pub fn high_poly(mut self, positions: &[[f32; 3]], normals: &[[f32; 3]], indices: &[u32]) -> Self {
+        if positions.len() != normals.len() {
+            self.report("every high poly position needs a normal");
+        } else if indices.len() % 3 != 0 || indices.iter().any(|&i| i as usize >= positions.len()) {
+            self.report("high poly indices must form a triangle list of its positions");
+        }
+        let verts = positions.iter().zip(normals).map(|(&pos, &normal)| Vertex::new(pos, normal)).collect();
+        self.high = Some((verts, indices.to_vec()));
+        self
+    }
+
+    /// Distance in front of baked points projection rays start from
+    pub fn cage_distance(mut self, distance: f32) -> Self {
+        self.settings.cage_distance = Some(distance);
+        self
+    }
+
+    /// Projection rays start from these positions of this mesh grown outwards instead.
+    /// Bakes fail if `positions` differ in length from positions of the mesh.
+    pub fn cage(mut self, positions: &[[f32; 3]]) -> Self {
+        if positions.len() != self.verts.len() {
+            self.report("every position needs a cage position");
+        }
+        self.cage = Some(self.verts.iter().zip(positions).map(|(vert, &pos)| Vertex { pos, ..*vert }).collect());
+        self
+    }
+
+    /// Replaces all settings at once
+    pub fn settings(mut self, settings: ComputeData) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.settings.samples = samples;
+        self
+    }
+
+    /// Cone angle of sampled rays in degrees
+    pub fn spread(mut self, spread: f32) -> Self {
+        self.settings.spread = spread;
+        self
+    }
+
+    pub fn distribution(mut self, distribution: Distribution) -> Self {
+        self.settings.distribution = distribution;
+        self
+    }
+
+    /// Sequence of ray directions, `cranley_patterson` offsets it for every vertex
+    pub fn sequence(mut self, sequence: Sequence, cranley_patterson: bool) -> Self {
+        self.settings.sequence = sequence;
+        self.settings.cranley_patterson = cranley_patterson;
+        self
+    }
+
+    /// Makes bake reproducible, output is identical for the same seed, input and settings
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.settings.seed = Some(seed);
+        self
+    }
+
+    /// Enables adaptive sampling, `samples` becomes the maximum sample count
+    pub fn adaptive(mut self, adaptive: Adaptive) -> Self {
+        self.settings.adaptive = Some(adaptive);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.settings.threads = threads;
+        self
+    }
+
+    pub fn accel(mut self, accel: AccelStructure) -> Self {
+        self.settings.accel = accel;
+        self
+    }
+
+    /// Limits distance of occluding geometry
+    pub fn max_distance(mut self, max_ray_dist: RayDistance) -> Self {
+        self.settings.max_ray_dist = max_ray_dist;
+        self
+    }
+
+    /// Weights occlusion by hit distance, needs `max_distance`
+    pub fn falloff(mut self, falloff: Falloff) -> Self {
+        self.settings.falloff = falloff;
+        self
+    }
+
+    /// Elements AO is baked for, corner and triangle targets give a value for every index instead of every vertex
+    pub fn target(mut self, target: BakeTarget) -> Self {
+        self.settings.target = target;
+        self
+    }
+
+    /// Quantity to bake, `bake` returns it instead of AO
+    pub fn mode(mut self, mode: BakeMode) -> Self {
+        self.settings.mode = mode;
+        self
+    }
+
+    /// Distances mapped from `min..max` to `0..=1` in `BakeMode::Thickness`, bakes fail unless `0 <= min < max`
+    pub fn thickness_range(mut self, min: f32, max: f32) -> Self {
+        self.settings.thickness_range = Some((min, max));
+        self
+    }
+
+    /// Radius of curvature mapped to 0 or 1 in `BakeMode::Curvature`
+    pub fn curvature_radius(mut self, radius: f32) -> Self {
+        self.settings.curvature_radius = Some(radius);
+        self
+    }
+
+    /// Also bakes bent normals, the average unoccluded directions, into `Texture::bent_normals` of texture bakes
+    pub fn bent_normals(mut self, enabled: bool) -> Self {
+        self.settings.bent_normals = enabled;
+        self
+    }
+
+    /// Places ground plane under the mesh which occludes AO without being baked itself
+    pub fn ground(mut self, ground: GroundPlane) -> Self {
+        self.settings.ground = Some(ground);
+        self
+    }
+
+    /// Handle which stops the bake when cancelled, the partial result is returned then
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Bakes on the calling thread, returns AO value in `0..=1` for every vertex, or every index with
+    /// corner and triangle targets. Fails for empty mesh or non finite vertices.
+    pub fn bake(&self) -> Result<Vec<f32>> {
+        self.bake_with_progress(|_| {})
+    }
+
+    /// Same as `bake`, `on_progress` is called after every sample
+    pub fn bake_with_progress<P: FnMut(&Progress)>(&self, on_progress: P) -> Result<Vec<f32>> {
+        let verts = self.bake_verts(&self.settings, on_progress)?;
+        Ok(verts.iter().map(|vert| vert.color[0]).collect())
+    }
+
+    /// Same as `bake`, also returns normalized bent normal of every value. Fully occluded vertices keep their normal.
+    pub fn bake_bent_normals(&self) -> Result<(Vec<f32>, Vec<[f32; 3]>)> {
+        let settings = ComputeData { bent_normals: true, ..self.settings };
+        let verts = self.bake_verts(&settings, |_| {})?;
+        Ok(verts.iter().map(|vert| (vert.color[0], vert.bent_normal)).unzip())
+    }
+
+    fn bake_verts<P: FnMut(&Progress)>(&self, settings: &ComputeData, mut on_progress: P) -> Result<Vec<Vertex>> {
+        self.check_geometry()?;
+        let split = |verts: &[Vertex]| -> Vec<Vertex> {
+            match settings.target {
+                BakeTarget::Vertex => verts.to_owned(),
+                _ => self.indices.iter().map(|&i| verts[i as usize]).collect()
+            }
+        };
+        let mut verts = split(&self.verts);
+        let indices = match settings.target {
+            BakeTarget::Vertex => self.indices.to_owned(),
+            _ => (0..self.indices.len() as u32).collect()
+        };
+        let on_progress = |progress: &Progress, _: &[Vertex]| on_progress(progress);
+        match &self.high {
+            Some((high_verts, high_indices)) => {
+                let cage = self.cage.as_deref().map(split);
+                let projection = Projection { verts: high_verts, indices: high_indices, cage: cage.as_deref() };
+                bake_projected(&mut verts, &indices, &projection, settings, &self.cancel, on_progress)?
+            },
+            None => bake(&mut verts, &indices, settings, &self.cancel, on_progress)?
+        };
+        Ok(verts)
+    }
+
+    /// Bakes into a `width` x `height` texture laid out by `uvs`, islands are padded by `padding` pixels.
+    /// Fails without `uvs` or when a side is zero or above `MAX_TEXTURE_SIZE`.
+    pub fn bake_texture(&self, width: u32, height: u32, padding: u32) -> Result<Texture> {
+        self.check_geometry()?;
+        self.check_uvs()?;
+        let size = (width, height);
+        let result = match &self.high {
+            Some((high_verts, high_indices)) => {
+                let projection = Projection { verts: high_verts, indices: high_indices, cage: self.cage.as_deref() };
+                bake_texture_projected((&self.verts, &self.indices), &projection, size, padding, &self.settings, &self.cancel, |_| {})
+            },
+            None => bake_texture(&self.verts, &self.indices, size, padding, &self.settings, &self.cancel, |_| {})
+        };
+        Ok(result?.0)
+    }
+
+    /// Bakes tangent space normals of `high_poly` mesh into a `width` x `height` texture laid out by `uvs`.
+    /// Fails without `uvs` or high poly mesh. Texels not reached when cancelled are flat.
+    pub fn bake_normal_map(&self, width: u32, height: u32, padding: u32) -> Result<NormalMap> {
+        self.check_geometry()?;
+        self.check_uvs()?;
+        let (high_verts, high_indices) = self.high.as_ref()
+            .ok_or_else(|| Error::UnsupportedGeometry("normal map needs a high poly mesh".to_string()))?;
+        let projection = Projection { verts: high_verts, indices: high_indices, cage: self.cage.as_deref() };
+        bake_normal_map((&self.verts, &self.indices), &projection, (width, height), padding, &self.settings, &self.cancel)
+    }
+
+    /// Keeps the first problem, later ones are often caused by it
+    fn report(&mut self, problem: &str) {
+        self.problem.get_or_insert_with(|| problem.to_string());
+    }
+
+    fn check_geometry(&self) -> Result<()> {
+        match &self.problem {
+            Some(problem) => Err(Error::UnsupportedGeometry(problem.clone())),
+            None => Ok(())
+        }
+    }
+
+    fn check_uvs(&self) -> Result<()> {
+        if self.has_uvs {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedGeometry("mesh has no texture coordinates".to_string()))
+        }
+    }
+
+    /// Bakes on a new thread
+    pub fn spawn<P>(self, on_progress: P) -> BakeHandle
+        where P: FnMut(&Progress) + Send + 'static
+    {
+        let cancel = self.cancel_token();
+        let thread = thread::spawn(move || self.bake_with_progress(on_progress));
+        BakeHandle { cancel, thread }
+    }
+}
+
+/// Bake running on background thread
+pub struct BakeHandle {
+    cancel: CancelToken,
+    thread: JoinHandle<Result<Vec<f32>>>
+}
+
+impl BakeHandle {
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Waits for bake to end, returns AO value for every vertex
+    pub fn join(self) -> Result<Vec<f32>> {
+        self.thread.join().expect("bake thread panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit square facing +Z
+    fn quad() -> Baker {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        Baker::new(&positions, &[[0.0, 0.0, 1.0]; 4], &[0, 1, 2, 0, 2, 3]).samples(16).seed(1)
+    }
+
+    #[test]
+    fn invalid_settings_fail() {
+        let is_invalid = |baker: Baker| matches!(baker.bake(), Err(Error::InvalidSettings(_)));
+        assert!(is_invalid(quad().samples(0)));
+        assert!(is_invalid(quad().max_distance(RayDistance::Absolute(-1.0))));
+        assert!(is_invalid(quad().spread(181.0)));
+        assert!(is_invalid(quad().target(BakeTarget::Triangle(0))));
+        assert!(is_invalid(quad().mode(BakeMode::Thickness).thickness_range(0.5, 0.5)));
+        assert!(is_invalid(quad().mode(BakeMode::Thickness).thickness_range(-1.0, 1.0)));
+        assert!(is_invalid(quad().falloff(Falloff::Linear)));
+        assert!(quad().bake().is_ok());
+        assert!(quad().spread(180.0).distribution(Distribution::PowerCosine(2.5)).bake().is_ok());
+    }
+
+    #[test]
+    fn mismatched_geometry_fails() {
+        let is_unsupported = |result: Result<Vec<f32>>| matches!(result, Err(Error::UnsupportedGeometry(_)));
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        assert!(is_unsupported(Baker::new(&positions, &[[0.0, 0.0, 1.0]; 2], &[0, 1, 2]).bake()));
+        assert!(is_unsupported(Baker::new(&positions, &[[0.0, 0.0, 1.0]; 3], &[0, 1]).bake()));
+        assert!(is_unsupported(Baker::new(&positions, &[[0.0, 0.0, 1.0]; 3], &[0, 1, 3]).bake()));
+        assert!(is_unsupported(quad().uvs(&[[0.0, 0.0]]).bake()));
+        assert!(is_unsupported(quad().cage(&[[0.0, 0.0, 1.0]]).bake()));
+    }
+
+    #[test]
+    fn clones_cancel_separately() {
+        let baker = quad();
+        let clone = baker.clone();
+        baker.cancel_token().cancel();
+        let samples_done = |baker: &Baker| {
+            let mut done = 0;
+            baker.bake_with_progress(|progress| done = progress.sample).unwrap();
+            done
+        };
+        assert_eq!(samples_done(&baker), 0);
+        assert_eq!(samples_done(&clone), 16);
+    }
+
+    #[test]
+    fn texture_needs_uvs() {
+        assert!(matches!(quad().bake_texture(8, 8, 0), Err(Error::UnsupportedGeometry(_))));
+        let uv_quad = quad().uvs(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        assert!(matches!(uv_quad.bake_texture(0, 8, 0), Err(Error::InvalidSettings(_))));
+        assert!(matches!(uv_quad.bake_texture(8, 100_000, 0), Err(Error::InvalidSettings(_))));
+        let texture = quad().uvs(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]).bake_texture(8, 8, 0).unwrap();
+        assert!(texture.pixels.iter().all(|&ao| ao > 0.0));
+    }
+
+    #[test]
+    fn cancelled_normal_map_is_flat() {
+        let positions = [[0.0, 0.0, 0.1], [1.0, 0.0, 0.1], [1.0, 1.0, 0.1], [0.0, 1.0, 0.1]];
+        let tilted = [0.0, 0.6, 0.8];
+        let baker = quad().uvs(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]])
+            .high_poly(&positions, &[tilted; 4], &[0, 1, 2, 0, 2, 3]).cage_distance(0.5);
+        let normal_map = baker.bake_normal_map(8, 8, 0).unwrap();
+        assert_eq!(normal_map.missed, 0);
+        assert!(normal_map.pixels.iter().all(|&normal| normal != [0.0, 0.0, 1.0]));
+
+        baker.cancel_token().cancel();
+        let normal_map = baker.bake_normal_map(8, 8, 0).unwrap();
+        assert!(normal_map.pixels.iter().all(|&normal| normal == [0.0, 0.0, 1.0]));
+    }
+}
@@ -0,0 +1,334 @@
+use std::path::{Path, PathBuf};
+
+use crate::io::load::{Loaders, ObjLoader};
+use crate::io::export::{export_baked, export_mesh, AoAttribute, ExportFormat};
+use crate::io::image::{pack_texture, save_normal_map, save_texture, Channel, ImageFormat, Packing};
+use crate::compute::{bake, bake_projected, print_progress, print_summary, Adaptive, BakeTarget, CancelToken, ComputeData, GroundPlane, Progress, Projection, RayDistance};
+use crate::compute::texture::{bake_normal_map, bake_texture, bake_texture_projected, check_size};
+use crate::consts::TEXTURE_PADDING;
+use crate::error::Error;
+
+const USAGE: &str = "usage: ao-baker bake <input.obj|input.ply|input.stl|input.gltf|input.glb> [-o <output.obj|output.ply|output.glb|output.png|output.pfm>] [--target <vertex|corner|triangle[:points]>] [--mode <ao|thickness|curvature|cavity>] [--thickness-range <min>:<max>] [--curvature-radius <d>] [--crease <degrees>] [--high <high.obj|...> [--cage <cage.obj|...> | --cage-distance <d>]] [--bent-normals] [--ground] [--ground-height <y>] [--ground-extent <d>] [--gltf-attribute <color|ao>] [--texture <size>] [--normal-map] [--padding <px>] [--16bit] [--pack <target.png>] [--channel <r|g|b|a>] [--samples <n>] [--spread <degrees>] [--distribution <uniform|cosine|power:<exponent>>] [--sequence <random|stratified|halton|sobol>] [--no-rotation] [--seed <n>] [--adaptive <tolerance>] [--min-samples <n>] [--threads <n>] [--accel <bvh|grid>] [--max-dist <d> | --max-dist-rel <fraction>] [--falloff <none|linear|quadratic|exp>] [--ascii]";
+
+struct BakeArgs {
+    input: PathBuf,
+    output: PathBuf,
+    format: ExportFormat,
+    texture: Option<TextureArgs>,
+    compute_data: ComputeData,
+    /// for OBJ files without normals
+    crease_angle: Option<f32>,
+    /// high poly mesh projected onto input
+    high: Option<PathBuf>,
+    /// input grown outwards, needs `high`
+    cage: Option<PathBuf>
+}
+
+struct TextureArgs {
+    size: u32,
+    padding: u32,
+    format: ImageFormat,
+    packing: Option<Packing>,
+    /// tangent space normals of high poly mesh instead of AO
+    normal_map: bool
+}
+
+/// Runs headless bake, `args` are command line arguments after `bake`.
+///
+/// Ctrl-C cancels the bake. Returns process exit code, 1 for cancelled bake, 2 for invalid arguments and `Error::exit_code` otherwise.
+pub fn run(args: &[String]) -> i32 {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    println!("opening {:?}", args.input);
+    let mut loaders = Loaders::default();
+    loaders.register(ObjLoader { crease_angle: args.crease_angle });
+    // texture bakes sample texels, bake target only changes vertex output
+    let split = args.texture.is_none() && args.compute_data.target != BakeTarget::Vertex;
+    let load = |path: &PathBuf| {
+        let mesh = loaders.load(path).map_err(|e| fail(&format!("failed to open {:?}", path), e))?;
+        for warning in &mesh.warnings {
+            println!("warning: {:?}: {}", path, warning);
+        }
+        Ok(mesh)
+    };
+    let mesh = match load(&args.input) {
+        Ok(mesh) => mesh,
+        Err(code) => return code
+    };
+    let high = match args.high.as_ref().map(load).transpose() {
+        Ok(high) => high,
+        Err(code) => return code
+    };
+    let cage = match args.cage.as_ref().map(load).transpose() {
+        Ok(cage) => cage,
+        Err(code) => return code
+    };
+    // split meshes all have the same indices, so triangles are compared before that
+    if cage.as_ref().is_some_and(|cage| cage.indices != mesh.indices) {
+        let e = Error::UnsupportedGeometry("cage doesn't match triangles of input".to_string());
+        return fail(&format!("failed to bake {:?}", args.input), e);
+    }
+    let (mesh, cage) = if split { (mesh.split_corners(), cage.map(|cage| cage.split_corners())) } else { (mesh, cage) };
+    let projection = high.as_ref().map(|high| Projection {
+        verts: &high.verts,
+        indices: &high.indices,
+        cage: cage.as_ref().map(|cage| cage.verts.as_slice())
+    });
+    let (mut verts, indices) = (mesh.verts, mesh.indices);
+
+    let cancel = CancelToken::new();
+    let handler_cancel = cancel.clone();
+    // first Ctrl-C stops the bake so the cancellation is reported, second one quits right away
+    let handler = ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            std::process::exit(1);
+        }
+        println!("cancelling bake");
+        handler_cancel.cancel();
+    });
+    if let Err(e) = handler {
+        eprintln!("failed to set Ctrl-C handler: {}", e);
+    }
+
+    if let Some(texture_args) = args.texture {
+        if !mesh.has_uvs {
+            let e = Error::UnsupportedGeometry("mesh has no texture coordinates".to_string());
+            return fail(&format!("failed to bake {:?}", args.input), e);
+        }
+        let size = (texture_args.size, texture_args.size);
+        if let (true, Some(projection)) = (texture_args.normal_map, &projection) {
+            let normal_map = match bake_normal_map((&verts, &indices), projection, size, texture_args.padding, &args.compute_data, &cancel) {
+                Ok(normal_map) => normal_map,
+                Err(e) => return fail(&format!("failed to bake {:?}", args.input), e)
+            };
+            if cancel.is_cancelled() {
+                println!("Bake aborted");
+                return 1;
+            }
+            if normal_map.missed > 0 {
+                println!("{} texels missed the high poly mesh, they are left flat", normal_map.missed);
+            }
+            return match save_normal_map(&args.output, normal_map.width, normal_map.height, &normal_map.pixels) {
+                Ok(()) => {
+                    println!("saved {:?}", args.output);
+                    0
+                },
+                Err(e) => fail(&format!("failed to save {:?}", args.output), e)
+            };
+        }
+        let result = match &projection {
+            Some(projection) => bake_texture_projected((&verts, &indices), projection, size, texture_args.padding, &args.compute_data, &cancel, print_progress),
+            None => bake_texture(&verts, &indices, size, texture_args.padding, &args.compute_data, &cancel, print_progress)
+        };
+        let (texture, progress) = match result {
+            Ok(result) => result,
+            Err(e) => return fail(&format!("failed to bake {:?}", args.input), e)
+        };
+        print_summary(&progress);
+        print_missed(&progress);
+        if !progress.is_finished() {
+            return 1;
+        }
+        let result = match &texture_args.packing {
+            Some(packing) => pack_texture(&args.output, packing, &texture),
+            None => save_texture(&args.output, texture_args.format, &texture)
+        };
+        if let Err(e) = result {
+            return fail(&format!("failed to save {:?}", args.output), e);
+        }
+        println!("saved {:?}", args.output);
+        if let Some(bent_normals) = &texture.bent_normals {
+            let path = bent_normal_path(&args.output);
+            if let Err(e) = save_normal_map(&path, texture.width, texture.height, bent_normals) {
+                return fail(&format!("failed to save {:?}", path), e);
+            }
+            println!("saved {:?}", path);
+        }
+        return 0;
+    }
+
+    let on_progress = |progress: &_, _: &_| print_progress(progress);
+    let result = match &projection {
+        Some(projection) => bake_projected(&mut verts, &indices, projection, &args.compute_data, &cancel, on_progress),
+        None => bake(&mut verts, &indices, &args.compute_data, &cancel, on_progress)
+    };
+    let progress = match result {
+        Ok(progress) => progress,
+        Err(e) => return fail(&format!("failed to bake {:?}", args.input), e)
+    };
+    print_summary(&progress);
+    print_missed(&progress);
+    if !progress.is_finished() {
+        return 1;
+    }
+
+    // split mesh no longer matches glTF input
+    let result = match args.compute_data.target {
+        BakeTarget::Vertex => export_baked(&args.output, args.format, &args.input, &verts, &indices, &mesh.objects),
+        _ => export_mesh(&args.output, args.format, &verts, &indices, &mesh.objects)
+    };
+    match result {
+        Ok(()) => {
+            println!("saved {:?}", args.output);
+            0
+        },
+        Err(e) => fail(&format!("failed to save {:?}", args.output), e)
+    }
+}
+
+/// Warns about points of projected bake which missed the high poly mesh
+fn print_missed(progress: &Progress) {
+    if progress.missed > 0 {
+        println!("{} points missed the high poly mesh, they are baked on their own surface", progress.missed);
+    }
+}
+
+/// Prints error with its causes, returns exit code of it
+fn fail(context: &str, error: Error) -> i32 {
+    eprintln!("error: {}: {}", context, error);
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        eprintln!("  caused by: {}", cause);
+        source = cause.source();
+    }
+    error.exit_code()
+}
+
+fn parse_args(args: &[String]) -> Result<BakeArgs, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut ascii = false;
+    let mut attribute = AoAttribute::default();
+    let mut texture_size = None;
+    let mut padding = TEXTURE_PADDING;
+    let mut sixteen_bit = false;
+    let mut pack_target = None;
+    let mut channel = Channel::default();
+    let mut compute_data = ComputeData::default();
+    let mut crease_angle = None;
+    let mut high = None;
+    let mut normal_map = false;
+    let mut cage = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--samples" => compute_data.samples = parse_value(&mut iter, arg)?,
+            "--spread" => compute_data.spread = parse_value(&mut iter, arg)?,
+            "--distribution" => compute_data.distribution = parse_value(&mut iter, arg)?,
+            "--sequence" => compute_data.sequence = parse_value(&mut iter, arg)?,
+            "--no-rotation" => compute_data.cranley_patterson = false,
+            "--seed" => compute_data.seed = Some(parse_value(&mut iter, arg)?),
+            "--adaptive" => compute_data.adaptive.get_or_insert_with(Adaptive::default).tolerance = parse_value(&mut iter, arg)?,
+            "--min-samples" => compute_data.adaptive.get_or_insert_with(Adaptive::default).min_samples = parse_value(&mut iter, arg)?,
+            "--threads" => compute_data.threads = parse_value(&mut iter, arg)?,
+            "--accel" => compute_data.accel = parse_value(&mut iter, arg)?,
+            "--max-dist" => compute_data.max_ray_dist = RayDistance::Absolute(parse_value(&mut iter, arg)?),
+            "--max-dist-rel" => compute_data.max_ray_dist = RayDistance::Relative(parse_value(&mut iter, arg)?),
+            "--falloff" => compute_data.falloff = parse_value(&mut iter, arg)?,
+            "--target" => compute_data.target = parse_value(&mut iter, arg)?,
+            "--crease" => crease_angle = Some(parse_value(&mut iter, arg)?),
+            "--bent-normals" => compute_data.bent_normals = true,
+            "--ground" => { compute_data.ground.get_or_insert_with(GroundPlane::default); },
+            "--ground-height" => compute_data.ground.get_or_insert_with(GroundPlane::default).height = Some(parse_value(&mut iter, arg)?),
+            "--ground-extent" => compute_data.ground.get_or_insert_with(GroundPlane::default).extent = Some(parse_value(&mut iter, arg)?),
+            "--high" => high = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--cage" => cage = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--cage-distance" => compute_data.cage_distance = Some(parse_value(&mut iter, arg)?),
+            "--mode" => compute_data.mode = parse_value(&mut iter, arg)?,
+            "--curvature-radius" => compute_data.curvature_radius = Some(parse_value(&mut iter, arg)?),
+            "--thickness-range" => compute_data.thickness_range = Some(parse_range(next_value(&mut iter, arg)?)?),
+            "--ascii" => ascii = true,
+            "--gltf-attribute" => attribute = parse_value(&mut iter, arg)?,
+            "--texture" => texture_size = Some(parse_value(&mut iter, arg)?),
+            "--normal-map" => normal_map = true,
+            "--padding" => padding = parse_value(&mut iter, arg)?,
+            "--16bit" => sixteen_bit = true,
+            "--pack" => pack_target = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--channel" => channel = parse_value(&mut iter, arg)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument `{}`", arg))
+        }
+    }
+
+    let input = input.ok_or_else(|| "missing input file".to_string())?;
+    let extension = if texture_size.is_some() { "png" } else { "ply" };
+    let suffix = if normal_map { "normal" } else { compute_data.mode.name() };
+    let output = output.unwrap_or_else(|| default_output(&input, suffix, extension));
+
+    let (format, image_format) = match output.extension().and_then(|ext| ext.to_str()) {
+        Some("obj") if texture_size.is_none() => (ExportFormat::Obj, ImageFormat::default()),
+        Some("ply") if texture_size.is_none() && ascii => (ExportFormat::PlyAscii, ImageFormat::default()),
+        Some("ply") if texture_size.is_none() => (ExportFormat::PlyBinary, ImageFormat::default()),
+        Some("glb") if texture_size.is_none() => (ExportFormat::Glb(attribute), ImageFormat::default()),
+        Some("png") if texture_size.is_some() && sixteen_bit => (ExportFormat::default(), ImageFormat::Png16),
+        Some("png") if texture_size.is_some() => (ExportFormat::default(), ImageFormat::Png8),
+        Some("pfm") if texture_size.is_some() && pack_target.is_none() => (ExportFormat::default(), ImageFormat::Pfm),
+        _ => return Err(format!("unsupported output format {:?}", output))
+    };
+
+    if let Some(size) = texture_size {
+        check_size((size, size)).map_err(|e| e.to_string())?;
+    }
+    if pack_target.is_some() && texture_size.is_none() {
+        return Err("`--pack` needs `--texture`".to_string());
+    }
+    if normal_map && (texture_size.is_none() || high.is_none()) {
+        return Err("`--normal-map` needs `--texture` and `--high`".to_string());
+    }
+    if normal_map && (pack_target.is_some() || image_format != ImageFormat::Png8) {
+        return Err("normal maps are saved as 8-bit .png only".to_string());
+    }
+    let packing = pack_target.map(|target| Packing { target, channel });
+    let texture = texture_size.map(|size| TextureArgs { size, padding, format: image_format, packing, normal_map });
+
+    compute_data.validate().map_err(|e| e.to_string())?;
+
+    if (cage.is_some() || compute_data.cage_distance.is_some()) && high.is_none() {
+        return Err("`--cage` and `--cage-distance` need `--high`".to_string());
+    }
+
+    if crease_angle.is_some_and(|angle: f32| !(0.0..=180.0).contains(&angle)) {
+        return Err("crease angle must be between 0 and 180 degrees".to_string());
+    }
+
+    Ok(BakeArgs { input, output, format, texture, compute_data, crease_angle, high, cage })
+}
+
+fn next_value<'a, I: Iterator<Item = &'a String>>(iter: &mut I, arg: &str) -> Result<&'a String, String> {
+    iter.next().ok_or_else(|| format!("missing value for `{}`", arg))
+}
+
+fn parse_value<'a, T, I>(iter: &mut I, arg: &str) -> Result<T, String>
+    where T: std::str::FromStr, I: Iterator<Item = &'a String>
+{
+    let value = next_value(iter, arg)?;
+    value.parse().map_err(|_| format!("invalid value `{}` for `{}`", value, arg))
+}
+
+/// Bent normals of texture bakes go next to the AO texture as `<name>_bent.png`
+fn bent_normal_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}_bent.png", stem))
+}
+
+/// Parses `<min>:<max>` distance range
+fn parse_range(value: &str) -> Result<(f32, f32), String> {
+    let invalid = || format!("invalid range `{}`", value);
+    let (min, max) = value.split_once(':').ok_or_else(invalid)?;
+    Ok((min.parse().map_err(|_| invalid())?, max.parse().map_err(|_| invalid())?))
+}
+
+/// Output is saved next to input as `<name>_<mode>.<ext>`
+fn default_output(input: &Path, mode: &str, extension: &str) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    input.with_file_name(format!("{}_{}.{}", stem, mode, extension))
+}
@@ -4,138 +4,683 @@ use std::time::Instant;
 use std::thread;
 use std::ops::{IndexMut, Index};
 use std::io::Write;
+use std::str::FromStr;
 
 use crate::render::VertexData;
-use crate::geo::Vertex;
+use crate::geo::{mean_curvature, Vertex};
 use crate::consts::*;
-
-use wavefront_obj::obj::{Object, Primitive, Shape, Vertex as ObjVertex};
+use crate::error::{Error, Result};
 
 use rand::thread_rng;
 use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 
 use cgmath::{Vector3, Quaternion, vec3, Matrix3, prelude::*};
 
-pub fn compute_ao(vertex_data: Arc<Mutex<VertexData>>, obj: Object, mut verts: Vec<Vertex>, bake_in_progress: Arc<AtomicBool>, compute_data: &ComputeData, bake_stopper: Arc<AtomicBool>) {
-    let compute_data = compute_data.clone();
+mod bvh;
+mod sequence;
+pub mod texture;
+
+use bvh::Bvh;
+use sequence::Sampler;
+
+pub use sequence::Sequence;
+
+/// Spawns bake thread, progress is pushed into renderer's `VertexData`. `on_error` receives the error
+/// when bake fails.
+pub fn compute_ao<F, E>(vertex_data: Arc<Mutex<VertexData>>, (mut verts, indices): (Vec<Vertex>, Vec<u32>), bake_in_progress: Arc<AtomicBool>, compute_data: &ComputeData, cancel: CancelToken, on_finish: F, on_error: E)
+    where F: FnOnce(&[Vertex]) + Send + 'static, E: FnOnce(Error) + Send + 'static
+{
+    let compute_data = *compute_data;
     thread::spawn(move || {
-        //let time = Instant::now();
-        let mut time = 0.0_f64;
-        let mut rng = thread_rng();
-        let spread = ANGLE_SPREAD / 180.0 * std::f32::consts::PI;
+        let result = bake(&mut verts, &indices, &compute_data, &cancel, |progress, verts| {
+            print_progress(progress);
+            vertex_data.lock().unwrap().update(verts.to_owned());
+        });
+        vertex_data.lock().unwrap().update(verts.to_owned());
+        match result {
+            Ok(progress) => {
+                print_summary(&progress);
+                if progress.is_finished() {
+                    on_finish(&verts);
+                }
+            },
+            Err(e) => on_error(e)
+        }
+        bake_in_progress.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Bakes AO, or other quantity of `compute_data.mode`, into vertex colors on the calling thread.
+///
+/// `on_progress` is called after every sample. Returns progress at the time bake ended, which is
+/// unfinished when bake was cancelled. Targets other than `BakeTarget::Vertex` expect mesh split by
+/// `Mesh::split_corners`, shared vertices get value of their last corner.
+pub fn bake<P>(verts: &mut [Vertex], indices: &[u32], compute_data: &ComputeData, cancel: &CancelToken, on_progress: P) -> Result<Progress>
+    where P: FnMut(&Progress, &[Vertex])
+{
+    bake_from(verts, indices, None, compute_data, cancel, on_progress)
+}
+
+/// Same as `bake`, but every baked point is first moved onto the surface of `projection` high poly mesh
+/// and only that mesh and the ground plane occlude, so the low poly mesh in `verts` receives detail of the high poly one.
+/// Curvature is computed from `verts` either way.
+pub fn bake_projected<P>(verts: &mut [Vertex], indices: &[u32], projection: &Projection, compute_data: &ComputeData, cancel: &CancelToken, on_progress: P) -> Result<Progress>
+    where P: FnMut(&Progress, &[Vertex])
+{
+    check_projection(verts, projection)?;
+    bake_from(verts, indices, Some(projection), compute_data, cancel, on_progress)
+}
+
+fn bake_from<P>(verts: &mut [Vertex], indices: &[u32], projection: Option<&Projection>, compute_data: &ComputeData, cancel: &CancelToken, mut on_progress: P) -> Result<Progress>
+    where P: FnMut(&Progress, &[Vertex])
+{
+    compute_data.validate()?;
+    check_mesh(verts, indices)?;
+    let target = compute_data.target;
+    if let BakeMode::Curvature = compute_data.mode {
+        let progress = bake_curvature(verts, indices, compute_data);
+        if let BakeTarget::Triangle(_) = target {
+            for tri in indices.chunks_exact(3) {
+                let curvature = tri.iter().map(|&i| verts[i as usize].color[0]).sum::<f32>() / 3.0;
+                for &index in tri {
+                    verts[index as usize].color = [curvature; 3];
+                }
+            }
+        }
+        on_progress(&progress, verts);
+        return Ok(progress);
+    }
+
+    let scene = Scene::new(occluders(verts, indices, projection), compute_data.accel, compute_data.ground);
+    if let (BakeTarget::Vertex, None) = (target, projection) {
+        return bake_points(verts, &scene, compute_data, cancel, on_progress);
+    }
+
+    let mut points = target_points(verts, indices, target);
+    let missed = projection.map_or(0, |projection| {
+        let cage = projection.cage.map(|cage| target_points(cage, indices, target));
+        project_points(&mut points, projection, cage.as_deref(), &scene, compute_data.cage_distance)
+    });
+    let mut progress = bake_points(&mut points, &scene, compute_data, cancel, |progress, points| {
+        resolve_points(verts, indices, points, target);
+        on_progress(progress, verts);
+    })?;
+    resolve_points(verts, indices, &points, target);
+    progress.missed = missed;
+    Ok(progress)
+}
 
-        let mut stdout =  std::io::stdout();
+/// Writes mean curvature into vertex colors, `1 / curvature_radius` of convex surface maps to 1, of
+/// concave to 0 and flat surface is 0.5. Needs no rays, so it's done in one step.
+fn bake_curvature(verts: &mut [Vertex], indices: &[u32], compute_data: &ComputeData) -> Progress {
+    let start = Instant::now();
+    let radius = compute_data.curvature_radius.unwrap_or_else(|| {
+        let extrema = find_extrema(&gather_triangles(verts, indices));
+        vec3(extrema[3] - extrema[0], extrema[4] - extrema[1], extrema[5] - extrema[2]).magnitude() * CURVATURE_RADIUS
+    });
+    let curvature = mean_curvature(verts, indices);
+    for (vert, curvature) in verts.iter_mut().zip(curvature) {
+        vert.color = [(0.5 + 0.5 * curvature * radius).clamp(0.0, 1.0); 3];
+    }
+    Progress { sample: 1, samples: 1, rays: 0, rays_saved: 0, elapsed: start.elapsed().as_secs_f64(), missed: 0 }
+}
 
-        let grid = AABBGrid::new(&verts, &obj.geometry[0].shapes, &obj.vertices);
+/// Surface points baked for the target, corner and triangle ones are oriented by their triangle
+fn target_points(verts: &[Vertex], indices: &[u32], target: BakeTarget) -> Vec<Vertex> {
+    if let BakeTarget::Vertex = target {
+        return verts.to_vec();
+    }
+    let mut points = Vec::new();
+    for tri in indices.chunks_exact(3) {
+        let corners = [verts[tri[0] as usize], verts[tri[1] as usize], verts[tri[2] as usize]];
+        let [a, b, c] = corners.map(|vert| Vector3::from(vert.pos));
+        let normal = (b - a).cross(c - a);
+        // degenerate triangles keep vertex normals
+        let normal = if normal.magnitude2() > 0.0 { Some(normal.normalize().into()) } else { None };
+
+        match target {
+            BakeTarget::Vertex => {},
+            BakeTarget::Corner => points.extend(corners.iter().map(|&vert| Vertex { normal: normal.unwrap_or(vert.normal), ..vert })),
+            BakeTarget::Triangle(count) => for i in 0..count {
+                // stratified along one axis and golden ratio sequence along the other, warped to cover triangle uniformly
+                let u = ((i as f32 + 0.5) / count as f32).sqrt();
+                let v = (i as f32 * 0.618_034).fract();
+                let pos = a * (1.0 - u) + b * (u * (1.0 - v)) + c * (u * v);
+                points.push(Vertex { pos: pos.into(), normal: normal.unwrap_or(corners[0].normal), ..corners[0] });
+            }
+        }
+    }
+    points
+}
 
-        for sample in 0..compute_data.samples {
-            if bake_stopper.load(Ordering::SeqCst) {
-                bake_stopper.store(false, Ordering::SeqCst);
-                print!("\nBake aborted after {} samples", sample);
-                break;
+/// Writes AO of baked `points` into colors of vertices they belong to
+fn resolve_points(verts: &mut [Vertex], indices: &[u32], points: &[Vertex], target: BakeTarget) {
+    match target {
+        BakeTarget::Vertex => for (vert, point) in verts.iter_mut().zip(points) {
+            vert.color = point.color;
+            vert.bent_normal = point.bent_normal;
+        },
+        BakeTarget::Corner => for (&index, point) in indices.iter().zip(points) {
+            verts[index as usize].color = point.color;
+            verts[index as usize].bent_normal = point.bent_normal;
+        },
+        BakeTarget::Triangle(count) => for (tri, points) in indices.chunks_exact(3).zip(points.chunks_exact(count as usize)) {
+            let ao = points.iter().map(|point| point.color[0]).sum::<f32>() / count as f32;
+            let bent_sum = points.iter().fold(Vector3::zero(), |sum, point| sum + Vector3::from(point.bent_normal));
+            let bent_normal = if bent_sum.magnitude2() > 0.0 { bent_sum.normalize().into() } else { [0.0; 3] };
+            for &index in tri {
+                verts[index as usize].color = [ao; 3];
+                verts[index as usize].bent_normal = bent_normal;
             }
-            let sample_time = Instant::now();
-            let line = get_random_ray(spread, &mut rng);
-            for vert in &mut verts {
-                let mut is_hit = false;
-
-                let offset = Vector3::from(vert.normal) * 0.001;
-                let orig = Vector3::from(vert.pos) + offset;
-                let q = Quaternion::from_arc(vec3(0.0, 0.0, 1.0), vert.normal.into(), None);
-                let mat = Matrix3::from(q);
-
-                let line = mat * line;
-                let cells = grid.traverse(&orig, &line);
-                'cells: for cell in &cells {
-                    let cell = &grid[*cell];
-                    let cell = match cell {
-                        Some(val) => val,
-                        None => continue
+        }
+    }
+}
+
+/// Checks that `indices` form non empty triangle list of vertices with finite positions and normals
+pub fn check_mesh(verts: &[Vertex], indices: &[u32]) -> Result<()> {
+    if indices.is_empty() {
+        return Err(Error::EmptyMesh);
+    }
+    if !indices.chunks_exact(3).remainder().is_empty() {
+        return Err(Error::UnsupportedGeometry("indices don't form triangle list".to_string()));
+    }
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= verts.len()) {
+        return Err(Error::UnsupportedGeometry(format!("index {} out of range", index)));
+    }
+    let is_finite = |v: &[f32; 3]| v.iter().all(|c| c.is_finite());
+    if let Some(i) = verts.iter().position(|vert| !is_finite(&vert.pos) || !is_finite(&vert.normal)) {
+        return Err(Error::UnsupportedGeometry(format!("vertex {} isn't finite", i)));
+    }
+    Ok(())
+}
+
+/// Checks high poly mesh of `projection` and that its cage matches baked `verts`
+fn check_projection(verts: &[Vertex], projection: &Projection) -> Result<()> {
+    check_mesh(projection.verts, projection.indices)?;
+    match projection.cage {
+        Some(cage) if cage.len() != verts.len() => Err(Error::UnsupportedGeometry(format!("cage has {} vertices, baked mesh {}", cage.len(), verts.len()))),
+        _ => Ok(())
+    }
+}
+
+/// Occluding geometry of the bake, the high poly mesh when projecting
+fn occluders(verts: &[Vertex], indices: &[u32], projection: Option<&Projection>) -> Vec<Triangle> {
+    match projection {
+        Some(projection) => gather_triangles(projection.verts, projection.indices),
+        None => gather_triangles(verts, indices)
+    }
+}
+
+/// Moves `points` onto the nearest surface of `scene` along lines from their `cage` positions through them,
+/// searched as far in front of them as the cage is and as far behind, or `cage_distance` along their normals
+/// without cage. Normals of moved points become normals of the high poly surface, points which miss it
+/// are left as they are and counted in the returned number. `scene` has to be made of `projection` triangles.
+fn project_points(points: &mut [Vertex], projection: &Projection, cage: Option<&[Vertex]>, scene: &Scene, cage_distance: Option<f32>) -> usize {
+    let cage_distance = cage_distance.unwrap_or(scene.diagonal * CAGE_DISTANCE);
+    points.par_iter_mut().enumerate().map(|(i, point)| {
+        let pos = Vector3::from(point.pos);
+        let normal = Vector3::from(point.normal);
+        let offset = cage.map_or(normal * cage_distance, |cage| Vector3::from(cage[i].pos) - pos);
+        // cage touching the surface falls back to the distance
+        let offset = if offset.magnitude2() > 0.0 { offset } else { normal * cage_distance };
+        let (dir, reach) = (offset.normalize(), offset.magnitude());
+
+        let front = scene.accel.closest_hit(pos, dir, reach);
+        let behind = scene.accel.closest_hit(pos, -dir, reach).map(|(dist, tri)| (-dist, tri));
+        let hit = match (front, behind) {
+            (Some(front), Some(behind)) => Some(if front.0 <= -behind.0 { front } else { behind }),
+            (front, behind) => front.or(behind)
+        };
+        match hit {
+            Some((dist, triangle)) => {
+                let hit = pos + dir * dist;
+                point.pos = hit.into();
+                point.normal = surface_normal(projection, triangle, hit, normal).into();
+                false
+            },
+            None => true
+        }
+    }).filter(|&missed| missed).count()
+}
+
+/// Normal of `projection` high poly surface at `hit` on its `triangle`, interpolated from its vertex normals,
+/// or face normal facing the side of `fallback` when they are missing
+fn surface_normal(projection: &Projection, triangle: usize, hit: Vector3<f32>, fallback: Vector3<f32>) -> Vector3<f32> {
+    let corners = [0, 1, 2].map(|k| &projection.verts[projection.indices[triangle * 3 + k] as usize]);
+    let [a, b, c] = corners.map(|vert| Vector3::from(vert.pos));
+    let face = (b - a).cross(c - a);
+    if face.magnitude2() == 0.0 {
+        return fallback;
+    }
+    // barycentric coordinates, all scaled by squared length of `face`
+    let weights = [(c - b).cross(hit - b).dot(face), (a - c).cross(hit - c).dot(face), (b - a).cross(hit - a).dot(face)];
+    let normal = corners.iter().zip(weights).fold(Vector3::zero(), |sum, (vert, weight)| sum + Vector3::from(vert.normal) * weight);
+    if normal.magnitude2() > 0.0 {
+        return normal.normalize();
+    }
+    let face = face.normalize();
+    if face.dot(fallback) < 0.0 { -face } else { face }
+}
+
+/// Occluding geometry prepared for ray queries
+struct Scene {
+    accel: Box<dyn Accel>,
+    /// length of bounding box diagonal
+    diagonal: f32,
+    /// ground plane placed under the geometry, only AO rays test it
+    ground: Option<Ground>
+}
+
+impl Scene {
+    fn new(triangles: Vec<Triangle>, structure: AccelStructure, ground: Option<GroundPlane>) -> Self {
+        let extrema = find_extrema(&triangles);
+        let diagonal = vec3(extrema[3] - extrema[0], extrema[4] - extrema[1], extrema[5] - extrema[2]).magnitude();
+        let ground = ground.map(|ground| Ground {
+            height: ground.height.unwrap_or(extrema[1]),
+            center: [(extrema[0] + extrema[3]) * 0.5, (extrema[2] + extrema[5]) * 0.5],
+            extent: ground.extent
+        });
+        let accel: Box<dyn Accel> = match structure {
+            AccelStructure::Grid => Box::new(AABBGrid::new(triangles)),
+            AccelStructure::Bvh => Box::new(Bvh::new(triangles))
+        };
+        Scene { accel, diagonal, ground }
+    }
+
+    /// Everything bake rays are tested against
+    fn occluders(&self) -> Occluders<'_> {
+        Occluders { accel: self.accel.as_ref(), ground: self.ground }
+    }
+}
+
+/// `GroundPlane` placed under scene geometry
+#[derive(Clone, Copy, Debug)]
+struct Ground {
+    height: f32,
+    /// x and z of the middle of the plane
+    center: [f32; 2],
+    extent: Option<f32>
+}
+
+impl Ground {
+    /// Distance to the ground along the ray, it's solid below its height, so rays starting below it hit
+    /// it right away and other rays only when travelling down
+    fn hit(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+        let dist = if orig.y < self.height {
+            0.0
+        } else if dir.y < 0.0 {
+            (self.height - orig.y) / dir.y
+        } else {
+            return None;
+        };
+        if dist >= max_dist {
+            return None;
+        }
+        let point = orig + dir * dist;
+        match self.extent {
+            Some(extent) if (point.x - self.center[0]).abs() > extent || (point.z - self.center[1]).abs() > extent => None,
+            _ => Some(dist)
+        }
+    }
+}
+
+/// Scene triangles together with its ground plane
+struct Occluders<'a> {
+    accel: &'a dyn Accel,
+    ground: Option<Ground>
+}
+
+impl Accel for Occluders<'_> {
+    fn occluded(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> bool {
+        self.ground.is_some_and(|ground| ground.hit(orig, dir, max_dist).is_some()) || self.accel.occluded(orig, dir, max_dist)
+    }
+
+    /// Ground hits report `usize::MAX` as triangle index
+    fn closest_hit(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<(f32, usize)> {
+        let ground = self.ground.and_then(|ground| ground.hit(orig, dir, max_dist));
+        self.accel.closest_hit(orig, dir, ground.unwrap_or(max_dist)).or(ground.map(|dist| (dist, usize::MAX)))
+    }
+}
+
+/// Bakes AO into colors of arbitrary surface points, `scene` is the occluding geometry
+fn bake_points<P>(verts: &mut [Vertex], scene: &Scene, compute_data: &ComputeData, cancel: &CancelToken, mut on_progress: P) -> Result<Progress>
+    where P: FnMut(&Progress, &[Vertex])
+{
+    let spread = compute_data.spread / 180.0 * std::f32::consts::PI;
+    let distribution = compute_data.distribution;
+
+    let pool = ThreadPoolBuilder::new().num_threads(compute_data.threads).build().map_err(std::io::Error::other)?;
+
+    let diagonal = scene.diagonal;
+    let max_dist = compute_data.max_ray_dist.resolve(diagonal);
+    let falloff = compute_data.falloff;
+    let mode = compute_data.mode;
+    // rays leaving through open geometry count as thick as the range allows
+    let thickness_range = compute_data.thickness_range.unwrap_or((0.0, max_dist.min(diagonal)));
+    let cavity_dist = match compute_data.max_ray_dist {
+        RayDistance::Unlimited => diagonal * CAVITY_DISTANCE,
+        dist => dist.resolve(diagonal)
+    };
+    // the ground only shades AO, thickness and cavity describe the mesh itself
+    let occluders = scene.occluders();
+    let triangles = scene.accel.as_ref();
+
+    // every random stream is derived from the seed, chunks don't depend on thread count
+    let seed = compute_data.seed.unwrap_or_else(|| thread_rng().gen());
+
+    // one rng stream per chunk, so every worker draws from its own generator
+    let chunk_count = verts.len() / CHUNK_SIZE + 1;
+    let mut rngs: Vec<ChaCha8Rng> = (0..chunk_count).map(|chunk| {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(chunk as u64 + 1);
+        rng
+    }).collect();
+
+    let sampler = Sampler::new(compute_data.sequence, compute_data.samples, &mut ChaCha8Rng::seed_from_u64(seed));
+    let cranley_patterson = compute_data.cranley_patterson;
+    let adaptive = compute_data.adaptive;
+    let bent_normals = compute_data.bent_normals;
+
+    let mut accumulators = vec![Accumulator::default(); verts.len()];
+
+    let mut progress = Progress {
+        sample: 0,
+        samples: compute_data.samples,
+        rays: 0,
+        rays_saved: 0,
+        elapsed: 0.0,
+        missed: 0
+    };
+
+    for sample in 0..compute_data.samples {
+        if cancel.is_cancelled() {
+            return Ok(progress);
+        }
+        let sample_time = Instant::now();
+        let rays: u64 = pool.install(|| {
+            verts.par_chunks_mut(CHUNK_SIZE).zip(accumulators.par_chunks_mut(CHUNK_SIZE)).zip(rngs.par_iter_mut()).enumerate().map(|(chunk_index, ((chunk, accs), rng))| {
+                let mut rays = 0;
+                for (i, (vert, acc)) in chunk.iter_mut().zip(accs.iter_mut()).enumerate() {
+                    if acc.converged {
+                        continue;
+                    }
+                    let rotation = if cranley_patterson {
+                        sequence::rotation(chunk_index * CHUNK_SIZE + i, seed)
+                    } else {
+                        [0.0; 2]
                     };
-                    for shape in cell {
-                        let shape = &obj.geometry[0].shapes[*shape];
-                        let shape = if let Primitive::Triangle(a, b, c) = shape.primitive {
-                            let v0 = vec3(obj.vertices[a.0].x as f32, obj.vertices[a.0].y as f32, obj.vertices[a.0].z as f32);
-                            let v1 = vec3(obj.vertices[b.0].x as f32, obj.vertices[b.0].y as f32, obj.vertices[b.0].z as f32);
-                            let v2 = vec3(obj.vertices[c.0].x as f32, obj.vertices[c.0].y as f32, obj.vertices[c.0].z as f32);
-                            [v0, v1, v2]
-                        } else {
-                            continue
-                        };
-
-                        is_hit = ray_triangle_intersect(orig, line, shape).is_some();
-
-                        if is_hit {
-                            break 'cells;
-                        }
+                    let (u, v) = sampler.point(sample, rotation, rng);
+                    let line = sample_direction(distribution, spread, u, v);
+                    match mode {
+                        BakeMode::Occlusion => {
+                            let dir = world_direction(vert.normal, line);
+                            let occlusion = occlusion(&occluders, vert, dir, max_dist, falloff);
+
+                            acc.add(1.0 - occlusion);
+                            if bent_normals {
+                                acc.add_direction(dir * (1.0 - occlusion));
+                                vert.bent_normal = acc.bent_normal(vert.normal);
+                            }
+                        },
+                        BakeMode::Thickness => {
+                            let dir = world_direction((-Vector3::from(vert.normal)).into(), line);
+                            acc.add(thickness(triangles, vert, dir, thickness_range));
+                        },
+                        BakeMode::Cavity => {
+                            let dir = world_direction(vert.normal, line);
+                            acc.add(cavity(triangles, vert, dir, cavity_dist));
+                        },
+                        // baked without rays by `bake_curvature`
+                        BakeMode::Curvature => {}
                     }
+                    if let Some(adaptive) = adaptive {
+                        acc.converged = acc.is_converged(&adaptive);
+                    }
+                    vert.color = [acc.mean; 3];
+                    rays += 1;
                 }
-                let old_color = vert.color[0];
-                let new_color = if is_hit {
-                    (old_color * sample as f32) / (sample as f32 + 1.0)
-                } else {
-                    (old_color * sample as f32 + 1.0) / (sample as f32 + 1.0)
-                };
-                vert.color = [new_color; 3];
-            }
-            time += sample_time.elapsed().as_secs_f64();
+                rays
+            }).sum()
+        });
+        progress.elapsed += sample_time.elapsed().as_secs_f64();
+        progress.sample = sample + 1;
+        progress.rays += rays;
+        progress.rays_saved += verts.len() as u64 - rays;
+
+        if rays == 0 {
+            // every vertex converged, rest of the samples is skipped
+            progress.rays_saved += (compute_data.samples - progress.sample) as u64 * verts.len() as u64;
+            progress.sample = compute_data.samples;
+        }
 
-            let rays =  (((sample + 1) as usize) * verts.len()) as f64;
+        on_progress(&progress, verts);
 
-            print!("\x1B[2KAverage {:.3} krays/s,  ETA: {:.1} secs\r", rays / time / 1_000.0, time * (compute_data.samples as f64 / sample as f64) - time);
-            stdout.flush().unwrap();
-            let mut lock = vertex_data.lock().unwrap();
-            lock.update(verts.to_owned());
+        if progress.is_finished() {
+            break;
         }
-        println!("\ncomp finished in {:.3} secs", time);
-        vertex_data.lock().unwrap().update(verts.to_owned());
-        bake_in_progress.store(false, Ordering::SeqCst);
-    });
+    }
+    Ok(progress)
 }
 
-fn find_extrema(verts: &[Vertex]) -> [f32; 6] {
-    let mut min_x = std::f32::MAX;
-    let mut min_y = std::f32::MAX;
-    let mut min_z = std::f32::MAX;
+/// Running mean and variance of samples of one vertex
+#[derive(Clone, Copy, Debug, Default)]
+struct Accumulator {
+    count: u32,
+    mean: f32,
+    /// sum of squared differences from mean
+    m2: f32,
+    /// sum of unoccluded directions weighted by their visibility
+    directions: [f32; 3],
+    converged: bool
+}
 
-    let mut max_x = std::f32::MIN;
-    let mut max_y = std::f32::MIN;
-    let mut max_z = std::f32::MIN;
+impl Accumulator {
+    fn add(&mut self, value: f32) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (value - self.mean);
+    }
 
-    for vert in verts {
-        if vert.pos[0] < min_x {
-            min_x = vert.pos[0];
+    fn add_direction(&mut self, dir: Vector3<f32>) {
+        self.directions = (Vector3::from(self.directions) + dir).into();
+    }
+
+    /// Normalized average unoccluded direction, `normal` when every ray was occluded
+    fn bent_normal(&self, normal: [f32; 3]) -> [f32; 3] {
+        let sum = Vector3::from(self.directions);
+        if sum.magnitude2() > 0.0 { sum.normalize().into() } else { normal }
+    }
+
+    /// Checks whether 95% confidence interval of mean is narrower than tolerance
+    fn is_converged(&self, adaptive: &Adaptive) -> bool {
+        if self.count < adaptive.min_samples.max(2) {
+            return false;
         }
-        if vert.pos[1] < min_y {
-            min_y = vert.pos[1];
+        let variance = self.m2 / (self.count - 1) as f32;
+        1.96 * (variance / self.count as f32).sqrt() < adaptive.tolerance
+    }
+}
+
+/// Prints progress line, overwriting the previous one
+pub fn print_progress(progress: &Progress) {
+    if progress.rays_saved > 0 {
+        print!("\x1B[2KAverage {:.3} krays/s,  ETA: {:.1} secs,  {:.1}% rays saved\r", progress.krays_per_sec(), progress.eta(), progress.saved_percent());
+    } else {
+        print!("\x1B[2KAverage {:.3} krays/s,  ETA: {:.1} secs\r", progress.krays_per_sec(), progress.eta());
+    }
+    std::io::stdout().flush().unwrap();
+}
+
+pub fn print_summary(progress: &Progress) {
+    if progress.is_finished() {
+        println!("\ncomp finished in {:.3} secs", progress.elapsed);
+    } else {
+        println!("\nBake aborted after {} samples", progress.sample);
+    }
+}
+
+/// State of running bake, reported after every sample
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    /// samples done so far
+    pub sample: u32,
+    /// total samples requested
+    pub samples: u32,
+    /// rays cast so far
+    pub rays: u64,
+    /// rays skipped by adaptive sampling for converged vertices
+    pub rays_saved: u64,
+    /// time spent tracing in seconds
+    pub elapsed: f64,
+    /// points of projected bakes which missed the high poly mesh and were baked on their own surface
+    pub missed: usize
+}
+
+impl Progress {
+    pub fn is_finished(&self) -> bool {
+        self.sample == self.samples
+    }
+
+    pub fn krays_per_sec(&self) -> f64 {
+        self.rays as f64 / self.elapsed / 1_000.0
+    }
+
+    pub fn saved_percent(&self) -> f64 {
+        self.rays_saved as f64 / (self.rays + self.rays_saved) as f64 * 100.0
+    }
+
+    /// estimated remaining time in seconds
+    pub fn eta(&self) -> f64 {
+        self.elapsed * (self.samples as f64 / self.sample as f64) - self.elapsed
+    }
+}
+
+/// Cloneable handle for stopping bake from another thread
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+type Triangle = [Vector3<f32>; 3];
+
+fn gather_triangles(verts: &[Vertex], indices: &[u32]) -> Vec<Triangle> {
+    indices.chunks_exact(3).map(|tri| {
+        [verts[tri[0] as usize].pos.into(), verts[tri[1] as usize].pos.into(), verts[tri[2] as usize].pos.into()]
+    }).collect()
+}
+
+/// Acceleration structure answering ray queries against scene triangles
+trait Accel: Sync {
+    /// Checks whether ray hits any triangle closer than `max_dist`
+    fn occluded(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> bool;
+
+    /// Distance to closest hit closer than `max_dist` and index of the triangle hit in the list accel was built from
+    fn closest_hit(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<(f32, usize)>;
+}
+
+/// Casts ray in tangent space direction `line` from vertex, returns occlusion in `0..=1`
+fn occlusion(accel: &dyn Accel, vert: &Vertex, dir: Vector3<f32>, max_dist: f32, falloff: Falloff) -> f32 {
+    let offset = Vector3::from(vert.normal) * 0.001;
+    let orig = Vector3::from(vert.pos) + offset;
+
+    if let Falloff::None = falloff {
+        // binary test is enough, any hit can end the traversal
+        return if accel.occluded(orig, dir, max_dist) { 1.0 } else { 0.0 };
+    }
+    match accel.closest_hit(orig, dir, max_dist) {
+        Some((dist, _)) => falloff.weight(dist / max_dist),
+        None => 0.0
+    }
+}
+
+/// Casts ray in world direction `dir` into the surface, returns distance to its other side mapped
+/// from `min..max` to `0..=1`
+fn thickness(accel: &dyn Accel, vert: &Vertex, dir: Vector3<f32>, (min, max): (f32, f32)) -> f32 {
+    let offset = Vector3::from(vert.normal) * 0.001;
+    let orig = Vector3::from(vert.pos) - offset;
+    let dist = accel.closest_hit(orig, dir, max).map_or(max, |(dist, _)| dist);
+    ((dist - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+/// Casts short ray in world direction `dir` out of the surface and mirrored one into it, returns 0 when
+/// only the outer one hits like in crevices, 1 when only the inner one hits like on ridges and 0.5 otherwise
+fn cavity(accel: &dyn Accel, vert: &Vertex, dir: Vector3<f32>, max_dist: f32) -> f32 {
+    let offset = Vector3::from(vert.normal) * 0.001;
+    let pos = Vector3::from(vert.pos);
+    let concave = accel.occluded(pos + offset, dir, max_dist);
+    let convex = accel.occluded(pos - offset, -dir, max_dist);
+    match (concave, convex) {
+        (true, false) => 0.0,
+        (false, true) => 1.0,
+        _ => 0.5
+    }
+}
+
+/// Rotates tangent space direction `line` around `normal`
+fn world_direction(normal: [f32; 3], line: Vector3<f32>) -> Vector3<f32> {
+    Matrix3::from(Quaternion::from_arc(vec3(0.0, 0.0, 1.0), normal.into(), None)) * line
+}
+
+fn find_extrema(triangles: &[Triangle]) -> [f32; 6] {
+    let mut min_x = f32::MAX;
+    let mut min_y = f32::MAX;
+    let mut min_z = f32::MAX;
+
+    let mut max_x = f32::MIN;
+    let mut max_y = f32::MIN;
+    let mut max_z = f32::MIN;
+
+    for vert in triangles.iter().flat_map(|tri| tri.iter()) {
+        if vert.x < min_x {
+            min_x = vert.x;
         }
-        if vert.pos[2] < min_z {
-            min_z = vert.pos[2];
+        if vert.y < min_y {
+            min_y = vert.y;
+        }
+        if vert.z < min_z {
+            min_z = vert.z;
         }
 
-        if vert.pos[0] > max_x {
-            max_x = vert.pos[0];
+        if vert.x > max_x {
+            max_x = vert.x;
         }
-        if vert.pos[1] > max_y {
-            max_y = vert.pos[1];
+        if vert.y > max_y {
+            max_y = vert.y;
         }
-        if vert.pos[2] > max_z {
-            max_z = vert.pos[2];
+        if vert.z > max_z {
+            max_z = vert.z;
         }
     }
     [min_x, min_y, min_z, max_x, max_y, max_z]
 }
 
-fn find_extrema_triangle(a: &ObjVertex, b: &ObjVertex, c: &ObjVertex) -> [f32; 6] {
-    let mut min_x = a.x;
-    let mut min_y = a.y;
-    let mut min_z = a.z;
+fn find_extrema_triangle(tri: &Triangle) -> [f32; 6] {
+    let mut min_x = tri[0].x;
+    let mut min_y = tri[0].y;
+    let mut min_z = tri[0].z;
 
-    let mut max_x = a.x;
-    let mut max_y = a.y;
-    let mut max_z = a.z;
+    let mut max_x = tri[0].x;
+    let mut max_y = tri[0].y;
+    let mut max_z = tri[0].z;
 
-    for vert in &[b, c] {
+    for vert in &tri[1..] {
         if vert.x < min_x {
             min_x = vert.x;
         }
@@ -156,7 +701,7 @@ fn find_extrema_triangle(a: &ObjVertex, b: &ObjVertex, c: &ObjVertex) -> [f32; 6
             max_z = vert.z;
         }
     }
-    [min_x as f32, min_y as f32, min_z as f32, max_x as f32, max_y as f32, max_z as f32]
+    [min_x, min_y, min_z, max_x, max_y, max_z]
 }
 
 fn map_pos_to_grid(pos: f32, divs: usize, min: f32, max: f32) -> usize {
@@ -178,14 +723,15 @@ struct AABBGrid {
     y_divs: usize,
     z_divs: usize,
     grid: Vec<Option<Vec<usize>>>,
-    max_dist: f32
+    max_dist: f32,
+    triangles: Vec<Triangle>
 }
 
 impl AABBGrid {
-    fn new(verts: &[Vertex], shapes: &[Shape], obj_verts: &[ObjVertex]) -> Self {
+    fn new(triangles: Vec<Triangle>) -> Self {
         let time = Instant::now();
 
-        let extrema = find_extrema(verts);
+        let extrema = find_extrema(&triangles);
 
         let size_x = extrema[3] - extrema[0];
         let size_y = extrema[4] - extrema[1];
@@ -194,14 +740,9 @@ impl AABBGrid {
         let sizes = [size_x, size_y, size_z];
 
         let mut dim = [1; 3];
-        let cube_root = (shapes.len() as f32 * 4.0 / (size_x * size_y * size_z)).powf(1.0 / 3.0);
+        let cube_root = (triangles.len() as f32 * 4.0 / (size_x * size_y * size_z)).powf(1.0 / 3.0);
         for i in 0..3 {
-            dim[i] = (cube_root * sizes[i]).floor() as usize;
-            if dim[i] > 128 {
-                dim[i] = 128;
-            } else if dim[i] < 1 {
-                dim[i] = 1;
-            }
+            dim[i] = ((cube_root * sizes[i]).floor() as usize).clamp(1, 128);
         }
 
         let max_dist = ((size_x.powi(2) + size_y.powi(2)).sqrt() + size_z.powi(2)).sqrt() + 1.0;
@@ -217,28 +758,27 @@ impl AABBGrid {
             y_divs: dim[1],
             z_divs: dim[2],
             grid,
-            max_dist
+            max_dist,
+            triangles: Vec::new()
         };
 
-        for (index, shape) in shapes.iter().enumerate() {
-            if let Primitive::Triangle(a, b, c) = shape.primitive {
-                let vert_extrema = find_extrema_triangle(&obj_verts[a.0], &obj_verts[b.0], &obj_verts[c.0]);
-                let min_index_x = map_pos_to_grid(vert_extrema[0], dim[0], extrema[0], extrema[3]);
-                let min_index_y = map_pos_to_grid(vert_extrema[1], dim[1], extrema[1], extrema[4]);
-                let min_index_z = map_pos_to_grid(vert_extrema[2], dim[2], extrema[2], extrema[5]);
-                let max_index_x = map_pos_to_grid(vert_extrema[3], dim[0], extrema[0], extrema[3]);
-                let max_index_y = map_pos_to_grid(vert_extrema[4], dim[1], extrema[1], extrema[4]);
-                let max_index_z = map_pos_to_grid(vert_extrema[5], dim[2], extrema[2], extrema[5]);
-                for x in min_index_x..=max_index_x {
-                    for y in min_index_y..=max_index_y {
-                        for z in min_index_z..=max_index_z {
-                            match &mut aabb_grid[(x, y, z)] {
-                                Some(vec) => {
-                                    vec.push(index);
-                                },
-                                None => {
-                                    aabb_grid[(x, y, z)] = Some(vec![index]);
-                                }
+        for (index, tri) in triangles.iter().enumerate() {
+            let vert_extrema = find_extrema_triangle(tri);
+            let min_index_x = map_pos_to_grid(vert_extrema[0], dim[0], extrema[0], extrema[3]);
+            let min_index_y = map_pos_to_grid(vert_extrema[1], dim[1], extrema[1], extrema[4]);
+            let min_index_z = map_pos_to_grid(vert_extrema[2], dim[2], extrema[2], extrema[5]);
+            let max_index_x = map_pos_to_grid(vert_extrema[3], dim[0], extrema[0], extrema[3]);
+            let max_index_y = map_pos_to_grid(vert_extrema[4], dim[1], extrema[1], extrema[4]);
+            let max_index_z = map_pos_to_grid(vert_extrema[5], dim[2], extrema[2], extrema[5]);
+            for x in min_index_x..=max_index_x {
+                for y in min_index_y..=max_index_y {
+                    for z in min_index_z..=max_index_z {
+                        match &mut aabb_grid[(x, y, z)] {
+                            Some(vec) => {
+                                vec.push(index);
+                            },
+                            None => {
+                                aabb_grid[(x, y, z)] = Some(vec![index]);
                             }
                         }
                     }
@@ -246,20 +786,22 @@ impl AABBGrid {
             }
         }
 
+        aabb_grid.triangles = triangles;
+
         let time = time.elapsed().as_secs_f64();
         println!("precompute took {:.03} secs", time);
 
         aabb_grid
     }
 
-    fn traverse(&self, origin: &Vector3<f32>, dir: &Vector3<f32>) -> Vec<(usize, usize, usize)> {
+    fn traverse(&self, origin: &Vector3<f32>, dir: &Vector3<f32>, max_dist: f32) -> Vec<(usize, usize, usize)> {
         let mut vec = Vec::with_capacity(self.x_divs + self.y_divs + self.z_divs);
 
 
         let inv_dir: Vector3<f32> = 1.0 / dir;
         let sign = [dir.x > 0.0, dir.y > 0.0, dir.z > 0.0];
 
-        let t_hit = match self.bounds.intersect(&origin, &inv_dir, &sign) {
+        let t_hit = match self.bounds.intersect(origin, &inv_dir, &sign) {
             Some(v) => v,
             None => return vec
         };
@@ -305,7 +847,7 @@ impl AABBGrid {
 
             let axis = MAP[k];
 
-            if self.max_dist < next_cross[axis] {
+            if max_dist < next_cross[axis] {
                 break
             }
 
@@ -321,6 +863,47 @@ impl AABBGrid {
     }
 }
 
+impl Accel for AABBGrid {
+    fn occluded(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> bool {
+        let cells = self.traverse(&orig, &dir, self.max_dist.min(max_dist));
+        for cell in &cells {
+            let cell = match &self[*cell] {
+                Some(val) => val,
+                None => continue
+            };
+            for triangle in cell {
+                match ray_triangle_intersect(orig, dir, self.triangles[*triangle]) {
+                    Some(dist) if dist < max_dist => return true,
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+
+    fn closest_hit(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<(f32, usize)> {
+        let mut closest = None;
+        let mut max_dist = max_dist;
+        let cells = self.traverse(&orig, &dir, self.max_dist.min(max_dist));
+        for cell in &cells {
+            let cell = match &self[*cell] {
+                Some(val) => val,
+                None => continue
+            };
+            for triangle in cell {
+                match ray_triangle_intersect(orig, dir, self.triangles[*triangle]) {
+                    Some(dist) if dist < max_dist => {
+                        max_dist = dist;
+                        closest = Some((dist, *triangle));
+                    },
+                    _ => {}
+                }
+            }
+        }
+        closest
+    }
+}
+
 impl Index<(usize, usize, usize)> for AABBGrid {
     type Output = Option<Vec<usize>>;
 
@@ -339,12 +922,17 @@ impl IndexMut<(usize, usize, usize)> for AABBGrid {
     }
 }
 
-pub fn get_random_ray(angle_spread: f32, rng: &mut ThreadRng) -> Vector3<f32> {
+/// Maps point from unit square to direction in cone around +Z axis
+pub fn sample_direction(distribution: Distribution, angle_spread: f32, u: f32, v: f32) -> Vector3<f32> {
     debug_assert!(angle_spread > 0.0);
-    debug_assert!(angle_spread < std::f32::consts::PI);
-
-    let angle = rng.gen_range((angle_spread / 2.0).cos(), 1.0);
-    let rot = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
+    debug_assert!(angle_spread <= std::f32::consts::PI);
+
+    // inverted CDF of cos^n over the cone, n = 0 gives uniform distribution
+    let power = distribution.exponent() + 1.0;
+    // cosine of half of 180 degrees comes out slightly negative, which fractional powers turn into NaN
+    let min_power = (angle_spread / 2.0).cos().max(0.0).powf(power);
+    let angle = (min_power + u * (1.0 - min_power)).powf(1.0 / power);
+    let rot = v * std::f32::consts::PI * 2.0;
     let one_minus_z = (1.0 - (angle).powi(2)).sqrt();
     vec3(one_minus_z * rot.cos(), one_minus_z * rot.sin(), angle)
 }
@@ -354,14 +942,14 @@ pub fn ray_triangle_intersect(orig: Vector3<f32>, dir: Vector3<f32>, vertices: [
     let v0v2 = vertices[2] - vertices[0];
     let pvec = dir.cross(v0v2);
     let det = v0v1.dot(pvec);
-    if det.abs() < std::f32::EPSILON {
+    if det.abs() < f32::EPSILON {
         return None;
     }
     let inv_det = 1.0 / det;
 
     let tvec = orig - vertices[0];
     let u = tvec.dot(pvec) * inv_det;
-    if u < 0.0 || u > 1.0 {
+    if !(0.0..=1.0).contains(&u) {
         return None;
     }
 
@@ -422,19 +1010,513 @@ impl BoundBox {
 
         Some(tmin)
     }
+
+    fn empty() -> Self {
+        BoundBox {
+            min: [f32::MAX; 3],
+            max: [f32::MIN; 3]
+        }
+    }
+
+    fn from_triangle(tri: &Triangle) -> Self {
+        let extrema = find_extrema_triangle(tri);
+        BoundBox {
+            min: [extrema[0], extrema[1], extrema[2]],
+            max: [extrema[3], extrema[4], extrema[5]]
+        }
+    }
+
+    fn grow(&self, point: Vector3<f32>) -> Self {
+        BoundBox {
+            min: [self.min[0].min(point.x), self.min[1].min(point.y), self.min[2].min(point.z)],
+            max: [self.max[0].max(point.x), self.max[1].max(point.y), self.max[2].max(point.z)]
+        }
+    }
+
+    fn union(&self, other: &BoundBox) -> Self {
+        BoundBox {
+            min: [self.min[0].min(other.min[0]), self.min[1].min(other.min[1]), self.min[2].min(other.min[2])],
+            max: [self.max[0].max(other.max[0]), self.max[1].max(other.max[1]), self.max[2].max(other.max[2])]
+        }
+    }
+
+    fn centroid(&self) -> Vector3<f32> {
+        vec3(self.min[0] + self.max[0], self.min[1] + self.max[1], self.min[2] + self.max[2]) * 0.5
+    }
+
+    fn surface_area(&self) -> f32 {
+        let x = (self.max[0] - self.min[0]).max(0.0);
+        let y = (self.max[1] - self.min[1]).max(0.0);
+        let z = (self.max[2] - self.min[2]).max(0.0);
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    fn longest_axis(&self) -> usize {
+        let x = self.max[0] - self.min[0];
+        let y = self.max[1] - self.min[1];
+        let z = self.max[2] - self.min[2];
+        if x >= y && x >= z {
+            0
+        } else if y >= z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test, checks whether ray enters box before `max_dist`
+    fn hit(&self, orig: &Vector3<f32>, inv_dir: &Vector3<f32>, max_dist: f32) -> bool {
+        let mut tmin = 0.0_f32;
+        let mut tmax = max_dist;
+        for i in 0..3 {
+            // ray parallel to the slab, zero times infinity of the distances would be NaN for rays on its planes
+            if inv_dir[i].is_infinite() {
+                if orig[i] < self.min[i] || orig[i] > self.max[i] {
+                    return false;
+                }
+                continue;
+            }
+            let t0 = (self.min[i] - orig[i]) * inv_dir[i];
+            let t1 = (self.max[i] - orig[i]) * inv_dir[i];
+            tmin = tmin.max(t0.min(t1));
+            tmax = tmax.min(t0.max(t1));
+        }
+        tmin <= tmax
+    }
 }
 
+/// Bake settings
 #[derive(Clone, Copy, Debug)]
 pub struct ComputeData {
-    max_ray_dist: f32,
-    samples: u32
+    /// geometry further than this does not occlude
+    pub max_ray_dist: RayDistance,
+    /// weighting of occlusion by hit distance
+    pub falloff: Falloff,
+    /// rays cast per vertex
+    pub samples: u32,
+    /// cone angle of sampled rays in degrees, 180 covers the whole hemisphere
+    pub spread: f32,
+    /// distribution of ray directions inside the cone
+    pub distribution: Distribution,
+    /// sequence of sample points mapped to ray directions
+    pub sequence: Sequence,
+    /// offsets sequence for every vertex, so neighbouring vertices don't share ray directions
+    pub cranley_patterson: bool,
+    /// seed for all random streams, same seed and settings give identical output,
+    /// random seed is picked for every bake when `None`
+    pub seed: Option<u64>,
+    /// stops sampling vertices once their value is known precisely enough, `samples` is the maximum then
+    pub adaptive: Option<Adaptive>,
+    /// number of worker threads used for tracing, defaults to all cores
+    pub threads: usize,
+    pub accel: AccelStructure,
+    pub target: BakeTarget,
+    /// quantity baked into vertex colors
+    pub mode: BakeMode,
+    /// distances mapped to `0..=1` by `BakeMode::Thickness`, from zero to max ray distance capped at
+    /// mesh bounding box diagonal when `None`
+    pub thickness_range: Option<(f32, f32)>,
+    /// radius of curvature mapped to 0 or 1 by `BakeMode::Curvature`, `CURVATURE_RADIUS` of mesh
+    /// bounding box diagonal when `None`
+    pub curvature_radius: Option<f32>,
+    /// distance in front of baked points projection rays start from without cage, `CAGE_DISTANCE` of high
+    /// poly bounding box diagonal when `None`
+    pub cage_distance: Option<f32>,
+    /// also bakes average unoccluded direction into `Vertex::bent_normal` in `BakeMode::Occlusion`
+    pub bent_normals: bool,
+    /// virtual floor occluding AO and bent normals without being part of the mesh
+    pub ground: Option<GroundPlane>
 }
 
 impl Default for ComputeData {
     fn default() -> Self {
         ComputeData{
-            max_ray_dist: std::f32::MAX,
-            samples: SAMPLES
+            max_ray_dist: RayDistance::Unlimited,
+            falloff: Falloff::None,
+            samples: SAMPLES,
+            spread: ANGLE_SPREAD,
+            distribution: Distribution::Uniform,
+            sequence: Sequence::Random,
+            cranley_patterson: true,
+            seed: None,
+            adaptive: None,
+            threads: num_cpus::get(),
+            accel: AccelStructure::Bvh,
+            target: BakeTarget::Vertex,
+            mode: BakeMode::Occlusion,
+            thickness_range: None,
+            curvature_radius: None,
+            cage_distance: None,
+            bent_normals: false,
+            ground: None
+        }
+    }
+}
+
+impl ComputeData {
+    /// Checks that settings are in their valid ranges, bakes fail with `Error::InvalidSettings` otherwise
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: &str| Err(Error::InvalidSettings(message.to_string()));
+        if self.samples == 0 {
+            return invalid("sample count must be positive");
+        }
+        if self.spread <= 0.0 || self.spread > 180.0 {
+            return invalid("spread must be above 0 and at most 180 degrees");
+        }
+        match self.max_ray_dist {
+            RayDistance::Absolute(dist) | RayDistance::Relative(dist) if dist <= 0.0 => return invalid("max distance must be positive"),
+            _ => {}
+        }
+        if self.falloff != Falloff::None && self.max_ray_dist == RayDistance::Unlimited {
+            return invalid("falloff needs limited max distance");
+        }
+        if let BakeTarget::Triangle(0) = self.target {
+            return invalid("triangle target needs at least one point");
+        }
+        if self.thickness_range.is_some_and(|(min, max)| min < 0.0 || max <= min) {
+            return invalid("thickness range must be increasing and not negative");
+        }
+        if self.curvature_radius.is_some_and(|radius| radius <= 0.0) {
+            return invalid("curvature radius must be positive");
+        }
+        if self.cage_distance.is_some_and(|distance| distance <= 0.0) {
+            return invalid("cage distance must be positive");
+        }
+        if self.ground.and_then(|ground| ground.extent).is_some_and(|extent| extent <= 0.0) {
+            return invalid("ground extent must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// High poly mesh projected onto the baked one by `bake_projected`
+#[derive(Clone, Copy, Debug)]
+pub struct Projection<'a> {
+    pub verts: &'a [Vertex],
+    pub indices: &'a [u32],
+    /// baked mesh grown outwards with the same vertex layout, rays search for the high poly surface from
+    /// its vertices towards the baked ones
+    pub cage: Option<&'a [Vertex]>
+}
+
+/// Horizontal plane occluding everything below it, like the floor a prop stands on
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GroundPlane {
+    /// height of the plane, lowest point of the occluding geometry when `None`
+    pub height: Option<f32>,
+    /// half of the side of square plane centered under the geometry, infinite when `None`
+    pub extent: Option<f32>
+}
+
+/// Settings of adaptive per-vertex sampling
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adaptive {
+    /// vertex is done when 95% confidence interval of its AO is narrower than this
+    pub tolerance: f32,
+    /// samples every vertex receives before convergence is checked
+    pub min_samples: u32
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Adaptive {
+            tolerance: ADAPTIVE_TOLERANCE,
+            min_samples: ADAPTIVE_MIN_SAMPLES
+        }
+    }
+}
+
+/// Elements AO is baked for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BakeTarget {
+    /// every unique vertex, triangles sharing a vertex share its AO
+    Vertex,
+    /// every triangle corner, sampled around normal of its triangle
+    Corner,
+    /// every triangle as flat value averaged over given number of points spread across its area
+    Triangle(u32)
+}
+
+impl BakeTarget {
+    /// next target, used for cycling through targets in window
+    pub fn next(self) -> Self {
+        match self {
+            BakeTarget::Vertex => BakeTarget::Corner,
+            BakeTarget::Corner => BakeTarget::Triangle(TRIANGLE_POINTS),
+            BakeTarget::Triangle(_) => BakeTarget::Vertex
+        }
+    }
+}
+
+impl FromStr for BakeTarget {
+    type Err = String;
+
+    /// Parses `vertex`, `corner`, `triangle` or `triangle:<points>`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "vertex" => Ok(BakeTarget::Vertex),
+            "corner" => Ok(BakeTarget::Corner),
+            "triangle" => Ok(BakeTarget::Triangle(TRIANGLE_POINTS)),
+            _ if s.starts_with("triangle:") => match s["triangle:".len()..].parse::<u32>() {
+                Ok(points) if points > 0 => Ok(BakeTarget::Triangle(points)),
+                _ => Err(format!("invalid point count in `{}`", s))
+            },
+            _ => Err(format!("unknown bake target `{}`", s))
+        }
+    }
+}
+
+/// Quantity baked for every point
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BakeMode {
+    /// ambient occlusion, 1 is fully unoccluded
+    Occlusion,
+    /// average distance to the other side of the mesh along inverted normal cone, used for
+    /// translucency, 0 is thin
+    Thickness,
+    /// signed mean curvature from mesh topology, 0.5 is flat and convex areas are brighter
+    Curvature,
+    /// signed short range occlusion, rays limited by max ray distance or `CAVITY_DISTANCE` of mesh
+    /// bounding box diagonal, 0.5 is flat, crevices are darker and ridges brighter
+    Cavity
+}
+
+impl BakeMode {
+    /// Suffix of output file names
+    pub fn name(self) -> &'static str {
+        match self {
+            BakeMode::Occlusion => "ao",
+            BakeMode::Thickness => "thickness",
+            BakeMode::Curvature => "curvature",
+            BakeMode::Cavity => "cavity"
+        }
+    }
+
+    /// next mode, used for cycling through modes in window
+    pub fn next(self) -> Self {
+        match self {
+            BakeMode::Occlusion => BakeMode::Thickness,
+            BakeMode::Thickness => BakeMode::Curvature,
+            BakeMode::Curvature => BakeMode::Cavity,
+            BakeMode::Cavity => BakeMode::Occlusion
+        }
+    }
+}
+
+impl FromStr for BakeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ao" => Ok(BakeMode::Occlusion),
+            "thickness" => Ok(BakeMode::Thickness),
+            "curvature" => Ok(BakeMode::Curvature),
+            "cavity" => Ok(BakeMode::Cavity),
+            _ => Err(format!("unknown bake mode `{}`", s))
+        }
+    }
+}
+
+/// Acceleration structure used for ray casting
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccelStructure {
+    /// uniform grid, capped at 128 cells per axis
+    Grid,
+    /// bounding volume hierarchy built with surface area heuristic
+    Bvh
+}
+
+impl FromStr for AccelStructure {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(AccelStructure::Grid),
+            "bvh" => Ok(AccelStructure::Bvh),
+            _ => Err(format!("unknown acceleration structure `{}`", s))
+        }
+    }
+}
+
+/// Maximum distance of occluding geometry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayDistance {
+    Unlimited,
+    /// distance in mesh units
+    Absolute(f32),
+    /// fraction of mesh bounding box diagonal
+    Relative(f32)
+}
+
+impl RayDistance {
+    fn resolve(self, diagonal: f32) -> f32 {
+        match self {
+            RayDistance::Unlimited => f32::MAX,
+            RayDistance::Absolute(dist) => dist,
+            RayDistance::Relative(fraction) => fraction * diagonal
+        }
+    }
+}
+
+/// Curve weighting occlusion by hit distance, needs limited `RayDistance`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    /// every hit fully occludes
+    None,
+    Linear,
+    Quadratic,
+    Exponential
+}
+
+impl Falloff {
+    /// Occlusion weight for hit at `dist` relative to max distance
+    fn weight(self, dist: f32) -> f32 {
+        match self {
+            Falloff::None => 1.0,
+            Falloff::Linear => 1.0 - dist,
+            Falloff::Quadratic => (1.0 - dist).powi(2),
+            Falloff::Exponential => (-EXP_FALLOFF * dist).exp()
+        }
+    }
+}
+
+impl FromStr for Falloff {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Falloff::None),
+            "linear" => Ok(Falloff::Linear),
+            "quadratic" => Ok(Falloff::Quadratic),
+            "exp" => Ok(Falloff::Exponential),
+            _ => Err(format!("unknown falloff `{}`", s))
+        }
+    }
+}
+
+/// Distribution of sampled ray directions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    /// same density in every direction
+    Uniform,
+    /// density proportional to cosine to normal, gives Lambert-weighted AO
+    Cosine,
+    /// density proportional to cosine raised to exponent
+    PowerCosine(f32)
+}
+
+impl Distribution {
+    fn exponent(self) -> f32 {
+        match self {
+            Distribution::Uniform => 0.0,
+            Distribution::Cosine => 1.0,
+            Distribution::PowerCosine(exponent) => exponent
+        }
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    /// Parses `uniform`, `cosine` or `power:<exponent>`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Distribution::Uniform),
+            "cosine" => Ok(Distribution::Cosine),
+            _ if s.starts_with("power:") => match s["power:".len()..].parse::<f32>() {
+                Ok(exponent) if exponent >= 0.0 => Ok(Distribution::PowerCosine(exponent)),
+                _ => Err(format!("invalid exponent in `{}`", s))
+            },
+            _ => Err(format!("unknown distribution `{}`", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Floor and wall meeting at a right angle, split into enough vertices for several chunks
+    fn corner() -> (Vec<Vertex>, Vec<u32>) {
+        const N: u32 = 40;
+        let mut verts = Vec::new();
+        let mut indices = Vec::new();
+        for wall in [false, true] {
+            let offset = verts.len() as u32;
+            let normal = if wall { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+            for i in 0..=N {
+                for j in 0..=N {
+                    let (u, v) = (i as f32 / N as f32, j as f32 / N as f32);
+                    let pos = if wall { [v, u, 0.0] } else { [u, 0.0, v] };
+                    verts.push(Vertex::new(pos, normal));
+                }
+            }
+            for i in 0..N {
+                for j in 0..N {
+                    let a = offset + i * (N + 1) + j;
+                    let (b, c, d) = (a + 1, a + N + 1, a + N + 2);
+                    indices.extend_from_slice(&[a, d, c, a, b, d]);
+                }
+            }
+        }
+        (verts, indices)
+    }
+
+    fn bake_with(compute_data: &ComputeData) -> Vec<u32> {
+        let (mut verts, indices) = corner();
+        bake(&mut verts, &indices, compute_data, &CancelToken::new(), |_, _| {}).unwrap();
+        verts.iter().map(|vert| vert.color[0].to_bits()).collect()
+    }
+
+    #[test]
+    fn seeded_bake_ignores_thread_count() {
+        let compute_data = ComputeData { samples: 16, seed: Some(3), threads: 1, ..ComputeData::default() };
+        let (verts, _) = corner();
+        assert!(verts.len() > 2 * CHUNK_SIZE);
+
+        let single = bake_with(&compute_data);
+        assert!(single.iter().any(|&ao| f32::from_bits(ao) < 1.0), "nothing was occluded");
+        assert_eq!(single, bake_with(&ComputeData { threads: 7, ..compute_data }));
+
+        let adaptive = ComputeData { adaptive: Some(Adaptive { tolerance: 0.1, min_samples: 4 }), sequence: Sequence::Sobol, ..compute_data };
+        assert_eq!(bake_with(&adaptive), bake_with(&ComputeData { threads: 3, ..adaptive }));
+    }
+
+    #[test]
+    fn ground_is_solid_below_its_height() {
+        let ground = Ground { height: 0.0, center: [0.0, 0.0], extent: Some(1.0) };
+        let (up, down) = (vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0));
+        assert_eq!(ground.hit(vec3(0.0, -0.5, 0.0), up, 10.0), Some(0.0));
+        assert_eq!(ground.hit(vec3(0.0, 2.0, 0.0), down, 10.0), Some(2.0));
+        assert_eq!(ground.hit(vec3(0.0, 2.0, 0.0), down, 1.0), None);
+        assert_eq!(ground.hit(vec3(0.0, 2.0, 0.0), up, 10.0), None);
+        assert_eq!(ground.hit(vec3(3.0, -0.5, 0.0), up, 10.0), None);
+    }
+
+    #[test]
+    fn ground_only_occludes_ao() {
+        let compute_data = ComputeData { samples: 8, seed: Some(3), ..ComputeData::default() };
+        let grounded = ComputeData { ground: Some(GroundPlane { height: Some(0.5), extent: None }), ..compute_data };
+        assert_ne!(bake_with(&compute_data), bake_with(&grounded));
+        for mode in [BakeMode::Thickness, BakeMode::Cavity] {
+            assert_eq!(bake_with(&ComputeData { mode, ..compute_data }), bake_with(&ComputeData { mode, ..grounded }));
+        }
+    }
+
+    #[test]
+    fn cosine_hemisphere_has_mean_cosine_of_two_thirds() {
+        const N: usize = 256;
+        let mut sum = 0.0;
+        for i in 0..N {
+            for j in 0..N {
+                let (u, v) = ((i as f32 + 0.5) / N as f32, (j as f32 + 0.5) / N as f32);
+                let dir = sample_direction(Distribution::Cosine, std::f32::consts::PI, u, v);
+                assert!(dir.z >= 0.0 && (dir.magnitude() - 1.0).abs() < 1e-4);
+                sum += dir.z;
+            }
         }
+        let mean = sum / (N * N) as f32;
+        assert!((mean - 2.0 / 3.0).abs() < 1e-3, "mean cosine {}", mean);
+        assert!(!sample_direction(Distribution::PowerCosine(2.5), std::f32::consts::PI, 0.0, 0.0).z.is_nan());
     }
 }
@@ -0,0 +1,317 @@
+use cgmath::Vector3;
+
+use super::{Accel, BoundBox, Triangle, ray_triangle_intersect};
+
+/// bins per axis used when evaluating splits
+const BINS: usize = 16;
+/// nodes with this many triangles or less are never split
+const MIN_LEAF_SIZE: usize = 2;
+/// nodes with more triangles are split even if SAH prefers a leaf
+const MAX_LEAF_SIZE: usize = 16;
+/// cost of node traversal relative to triangle intersection
+const TRAVERSAL_COST: f32 = 1.0;
+const STACK_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    bounds: BoundBox,
+    /// first triangle for leaves, index of second child for interior nodes,
+    /// first child immediately follows its parent
+    offset: u32,
+    /// triangle count, zero for interior nodes
+    count: u32,
+}
+
+struct BuildItem {
+    bounds: BoundBox,
+    centroid: Vector3<f32>,
+    index: usize
+}
+
+/// Bounding volume hierarchy flattened in depth-first order
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// triangles ordered so that every leaf references continuous range
+    triangles: Vec<Triangle>,
+    /// index of every triangle in the list the hierarchy was built from
+    order: Vec<u32>,
+    /// split axis of interior nodes, kept apart so nodes stay 32 bytes
+    axes: Vec<u8>
+}
+
+impl Bvh {
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        let mut items: Vec<BuildItem> = triangles.iter().enumerate().map(|(index, tri)| {
+            let bounds = BoundBox::from_triangle(tri);
+            BuildItem { bounds, centroid: bounds.centroid(), index }
+        }).collect();
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(triangles.len() * 2),
+            triangles: Vec::with_capacity(triangles.len()),
+            order: Vec::with_capacity(triangles.len()),
+            axes: Vec::with_capacity(triangles.len() * 2)
+        };
+
+        if !items.is_empty() {
+            bvh.build(&mut items, 0, 1);
+        }
+        bvh.triangles = items.iter().map(|item| triangles[item.index]).collect();
+        bvh.order = items.iter().map(|item| item.index as u32).collect();
+
+        bvh
+    }
+
+    fn build(&mut self, items: &mut [BuildItem], first: usize, depth: usize) {
+        let bounds = items.iter().fold(BoundBox::empty(), |acc, item| acc.union(&item.bounds));
+        let node_index = self.nodes.len();
+        self.nodes.push(Node { bounds, offset: first as u32, count: items.len() as u32 });
+        self.axes.push(0);
+
+        if items.len() <= MIN_LEAF_SIZE || depth >= STACK_SIZE {
+            return;
+        }
+
+        let (axis, mid) = match find_split(items, &bounds) {
+            Some(split) => split,
+            None if items.len() <= MAX_LEAF_SIZE => return,
+            None => split_median(items)
+        };
+
+        self.build(&mut items[..mid], first, depth + 1);
+        let second = self.nodes.len();
+        self.build(&mut items[mid..], first + mid, depth + 1);
+
+        self.nodes[node_index].offset = second as u32;
+        self.nodes[node_index].count = 0;
+        self.axes[node_index] = axis as u8;
+    }
+}
+
+/// Finds split with lowest SAH cost and partitions `items` by it.
+///
+/// Returns split axis and index of first item of the second half, or `None` when leaf is cheaper.
+fn find_split(items: &mut [BuildItem], bounds: &BoundBox) -> Option<(usize, usize)> {
+    let centroid_bounds = items.iter().fold(BoundBox::empty(), |acc, item| acc.grow(item.centroid));
+
+    let mut best: Option<(usize, usize)> = None;
+    let mut best_cost = items.len() as f32;
+    let parent_area = bounds.surface_area();
+
+    for axis in 0..3 {
+        let min = centroid_bounds.min[axis];
+        let extent = centroid_bounds.max[axis] - min;
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0usize; BINS];
+        let mut bin_bounds = [BoundBox::empty(); BINS];
+        for item in items.iter() {
+            let bin = bin_index(item.centroid[axis], min, extent);
+            counts[bin] += 1;
+            bin_bounds[bin] = bin_bounds[bin].union(&item.bounds);
+        }
+
+        // sweep from right to get area and count of everything right of each split plane
+        let mut right_area = [0.0; BINS];
+        let mut right_count = [0; BINS];
+        let mut acc = BoundBox::empty();
+        let mut count = 0;
+        for bin in (1..BINS).rev() {
+            acc = acc.union(&bin_bounds[bin]);
+            count += counts[bin];
+            right_area[bin] = acc.surface_area();
+            right_count[bin] = count;
+        }
+
+        let mut acc = BoundBox::empty();
+        let mut count = 0;
+        for split in 1..BINS {
+            acc = acc.union(&bin_bounds[split - 1]);
+            count += counts[split - 1];
+            if count == 0 || right_count[split] == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST + (acc.surface_area() * count as f32 + right_area[split] * right_count[split] as f32) / parent_area;
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, split));
+            }
+        }
+    }
+
+    let (axis, split) = best?;
+    let min = centroid_bounds.min[axis];
+    let extent = centroid_bounds.max[axis] - min;
+
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if bin_index(items[i].centroid[axis], min, extent) < split {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some((axis, mid))
+}
+
+/// Fallback for items with overlapping centroids, halves them along longest axis
+fn split_median(items: &mut [BuildItem]) -> (usize, usize) {
+    let centroid_bounds = items.iter().fold(BoundBox::empty(), |acc, item| acc.grow(item.centroid));
+    let axis = centroid_bounds.longest_axis();
+    items.sort_by(|a, b| a.centroid[axis].partial_cmp(&b.centroid[axis]).unwrap_or(std::cmp::Ordering::Equal));
+    (axis, items.len() / 2)
+}
+
+fn bin_index(pos: f32, min: f32, extent: f32) -> usize {
+    let bin = ((pos - min) / extent * BINS as f32) as usize;
+    bin.min(BINS - 1)
+}
+
+impl Accel for Bvh {
+    fn occluded(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> bool {
+        self.traverse(orig, dir, max_dist, true).is_some()
+    }
+
+    fn closest_hit(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<(f32, usize)> {
+        self.traverse(orig, dir, max_dist, false)
+    }
+}
+
+impl Bvh {
+    /// Finds hit closer than `max_dist`, with `any_hit` first found hit ends the traversal
+    fn traverse(&self, orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32, any_hit: bool) -> Option<(f32, usize)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inv_dir: Vector3<f32> = 1.0 / dir;
+        let dir_neg = [dir.x < 0.0, dir.y < 0.0, dir.z < 0.0];
+
+        let mut closest = None;
+        let mut max_dist = max_dist;
+
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut index = 0;
+
+        loop {
+            let node = &self.nodes[index];
+            if node.bounds.hit(&orig, &inv_dir, max_dist) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for (i, tri) in self.triangles[start..start + node.count as usize].iter().enumerate() {
+                        match ray_triangle_intersect(orig, dir, *tri) {
+                            Some(dist) if dist < max_dist => {
+                                let index = self.order[start + i] as usize;
+                                if any_hit {
+                                    return Some((dist, index));
+                                }
+                                max_dist = dist;
+                                closest = Some((dist, index));
+                            },
+                            _ => {}
+                        }
+                    }
+                } else {
+                    // visit near child first, far one goes on stack
+                    let (near, far) = if dir_neg[self.axes[index] as usize] {
+                        (node.offset as usize, index + 1)
+                    } else {
+                        (index + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far as u32;
+                    stack_len += 1;
+                    index = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            index = stack[stack_len] as usize;
+        }
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+    use cgmath::{vec3, InnerSpace};
+
+    use super::*;
+
+    /// Closest hit found by testing every triangle
+    fn brute_force(triangles: &[Triangle], orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+        triangles.iter().filter_map(|&tri| ray_triangle_intersect(orig, dir, tri)).filter(|&dist| dist < max_dist).min_by(|a, b| a.partial_cmp(b).unwrap())
+    }
+
+    /// Height field of 16 x 16 quads over the unit square, its edges lie on bin boundaries, and random triangles above it
+    fn scene(rng: &mut StdRng) -> Vec<Triangle> {
+        let height = |x: usize, z: usize| ((x * 7 + z * 3) % 5) as f32 * 0.02;
+        let point = |x: usize, z: usize| vec3(x as f32 / 16.0, height(x, z), z as f32 / 16.0);
+        let mut triangles = Vec::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                triangles.push([point(x, z), point(x + 1, z), point(x + 1, z + 1)]);
+                triangles.push([point(x, z), point(x + 1, z + 1), point(x, z + 1)]);
+            }
+        }
+        for _ in 0..200 {
+            let center = vec3(rng.gen(), rng.gen::<f32>() + 0.2, rng.gen());
+            triangles.push([0, 1, 2].map(|_| center + vec3(rng.gen(), rng.gen(), rng.gen()) * 0.1));
+        }
+        triangles
+    }
+
+    fn assert_matches(bvh: &Bvh, triangles: &[Triangle], orig: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) {
+        let expected = brute_force(triangles, orig, dir, max_dist);
+        let hit = bvh.closest_hit(orig, dir, max_dist);
+        assert_eq!(hit.map(|(dist, _)| dist), expected, "ray from {:?} along {:?}", orig, dir);
+        if let Some((dist, index)) = hit {
+            assert_eq!(ray_triangle_intersect(orig, dir, triangles[index]), Some(dist), "wrong triangle index");
+        }
+        assert_eq!(bvh.occluded(orig, dir, max_dist), expected.is_some(), "ray from {:?} along {:?}", orig, dir);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let triangles = scene(&mut rng);
+        let bvh = Bvh::new(triangles.clone());
+
+        for _ in 0..2000 {
+            let orig = vec3(rng.gen::<f32>() * 1.4 - 0.2, rng.gen::<f32>() * 1.4 - 0.2, rng.gen::<f32>() * 1.4 - 0.2);
+            let dir = vec3(rng.gen::<f32>() - 0.5, rng.gen::<f32>() - 0.5, rng.gen::<f32>() - 0.5);
+            if dir.magnitude2() == 0.0 {
+                continue;
+            }
+            let max_dist = if rng.gen() { f32::MAX } else { rng.gen::<f32>() };
+            assert_matches(&bvh, &triangles, orig, dir.normalize(), max_dist);
+        }
+    }
+
+    #[test]
+    fn matches_brute_force_on_bin_boundaries() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let triangles = scene(&mut rng);
+        let bvh = Bvh::new(triangles.clone());
+
+        // rays lying in planes through grid edges, with zero direction components
+        for k in 0..=16 {
+            let edge = k as f32 / 16.0;
+            for _ in 0..50 {
+                let (a, b) = (rng.gen::<f32>() - 0.5, rng.gen::<f32>());
+                let down = vec3(0.0, -1.0, 0.0);
+                assert_matches(&bvh, &triangles, vec3(edge, 2.0, rng.gen()), down, f32::MAX);
+                assert_matches(&bvh, &triangles, vec3(rng.gen(), 2.0, edge), down, f32::MAX);
+                assert_matches(&bvh, &triangles, vec3(edge, 0.5, rng.gen()), vec3(0.0, -b, a).normalize(), f32::MAX);
+                assert_matches(&bvh, &triangles, vec3(rng.gen(), 0.5, edge), vec3(a, -b, 0.0).normalize(), f32::MAX);
+                assert_matches(&bvh, &triangles, vec3(-0.5, 0.01, edge), vec3(1.0, 0.0, 0.0), f32::MAX);
+            }
+        }
+    }
+}
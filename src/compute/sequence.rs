@@ -0,0 +1,157 @@
+use std::str::FromStr;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+/// Sequence of points in unit square used for ray directions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sequence {
+    /// independent random points
+    Random,
+    /// one jittered point in every cell of a grid over the unit square
+    Stratified,
+    /// Halton sequence in bases 2 and 3
+    Halton,
+    /// first two dimensions of Sobol sequence
+    Sobol
+}
+
+impl FromStr for Sequence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Sequence::Random),
+            "stratified" => Ok(Sequence::Stratified),
+            "halton" => Ok(Sequence::Halton),
+            "sobol" => Ok(Sequence::Sobol),
+            _ => Err(format!("unknown sequence `{}`", s))
+        }
+    }
+}
+
+/// Generates sample points of chosen sequence, shared by all vertices of a bake
+pub struct Sampler {
+    sequence: Sequence,
+    /// columns of stratification grid
+    strata_x: u32,
+    strata_y: u32,
+    /// order in which strata are visited, so partial bakes are spread over whole square
+    strata_order: Vec<u32>
+}
+
+impl Sampler {
+    pub fn new<R: Rng>(sequence: Sequence, samples: u32, rng: &mut R) -> Self {
+        let (strata_x, strata_y, strata_order) = if let Sequence::Stratified = sequence {
+            // closest factor pair gives every sample its own stratum
+            let samples = samples.max(1);
+            let strata_x = (1..=samples).take_while(|x| x * x <= samples).filter(|x| samples % x == 0).last().unwrap_or(1);
+            let strata_y = samples / strata_x;
+            let mut order: Vec<u32> = (0..strata_x * strata_y).collect();
+            order.shuffle(rng);
+            (strata_x, strata_y, order)
+        } else {
+            (1, 1, Vec::new())
+        };
+
+        Sampler { sequence, strata_x, strata_y, strata_order }
+    }
+
+    /// Point for `sample`-th ray, `rotation` is Cranley-Patterson offset of the vertex
+    pub fn point<R: Rng>(&self, sample: u32, rotation: [f32; 2], rng: &mut R) -> (f32, f32) {
+        let (u, v) = match self.sequence {
+            Sequence::Random => (rng.gen(), rng.gen()),
+            Sequence::Stratified => {
+                let cell = self.strata_order[sample as usize % self.strata_order.len()];
+                let x = (cell % self.strata_x) as f32 + rng.gen::<f32>();
+                let y = (cell / self.strata_x) as f32 + rng.gen::<f32>();
+                (x / self.strata_x as f32, y / self.strata_y as f32)
+            },
+            Sequence::Halton => (radical_inverse(sample, 2), radical_inverse(sample, 3)),
+            Sequence::Sobol => sobol(sample)
+        };
+        (wrap(u + rotation[0]), wrap(v + rotation[1]))
+    }
+}
+
+/// Pseudo-random offset in unit square for given vertex, decorrelates neighbouring vertices
+pub fn rotation(index: usize, seed: u64) -> [f32; 2] {
+    let a = hash(index as u32 ^ hash(seed as u32 ^ hash((seed >> 32) as u32)));
+    let b = hash(a);
+    [to_unit(a), to_unit(b)]
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut factor = inv_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inv_base;
+    }
+    result as f32
+}
+
+fn sobol(index: u32) -> (f32, f32) {
+    // first dimension is van der Corput sequence
+    let u = index.reverse_bits();
+
+    // direction numbers of second dimension from primitive polynomial x + 1
+    let mut v = 0;
+    let mut direction = 1 << 31;
+    let mut index = index;
+    while index > 0 {
+        if index & 1 == 1 {
+            v ^= direction;
+        }
+        direction ^= direction >> 1;
+        index >>= 1;
+    }
+    (to_unit(u), to_unit(v))
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+fn to_unit(x: u32) -> f32 {
+    // top 24 bits fit exactly into f32 mantissa, keeps result below 1
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+fn wrap(x: f32) -> f32 {
+    if x >= 1.0 {
+        x - 1.0
+    } else {
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn stratified_samples_fill_every_stratum_once() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for &samples in &[1, 12, 13, 16, 100] {
+            let sampler = Sampler::new(Sequence::Stratified, samples, &mut rng);
+            assert_eq!(sampler.strata_x * sampler.strata_y, samples);
+            let mut cells: Vec<(u32, u32)> = (0..samples).map(|sample| {
+                let (u, v) = sampler.point(sample, [0.0; 2], &mut rng);
+                ((u * sampler.strata_x as f32) as u32, (v * sampler.strata_y as f32) as u32)
+            }).collect();
+            cells.sort_unstable();
+            cells.dedup();
+            assert_eq!(cells.len(), samples as usize);
+        }
+    }
+}
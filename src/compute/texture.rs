@@ -0,0 +1,308 @@
+//! Texture space baking, AO or normals are computed for every texel covered by mesh's UV layout
+
+use std::ops::{Add, Mul};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use cgmath::{vec3, InnerSpace, Vector3, Zero};
+
+use crate::geo::{tangents, Vertex};
+use crate::consts::{MAX_TEXTURE_SIZE, TEXTURE_PADDING};
+use crate::error::{Error, Result};
+
+use super::{bake_curvature, bake_points, check_mesh, check_projection, occluders, print_progress, print_summary, project_points};
+use super::{BakeMode, CancelToken, ComputeData, Progress, Projection, Scene};
+
+/// Single channel float image, rows go from top to bottom
+#[derive(Clone, Debug)]
+pub struct Texture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+    /// object space bent normal of every pixel when baked, zero outside of islands
+    pub bent_normals: Option<Vec<[f32; 3]>>
+}
+
+/// Tangent space normal map, rows go from top to bottom
+#[derive(Clone, Debug)]
+pub struct NormalMap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 3]>,
+    /// texels which missed the high poly mesh and were left flat
+    pub missed: usize
+}
+
+/// Spawns texture bake thread, `on_finish` receives the texture if bake wasn't cancelled and `on_error`
+/// the error if it failed
+pub fn compute_texture<F, E>((verts, indices): (Vec<Vertex>, Vec<u32>), size: u32, bake_in_progress: Arc<AtomicBool>, compute_data: &ComputeData, cancel: CancelToken, on_finish: F, on_error: E)
+    where F: FnOnce(&Texture) + Send + 'static, E: FnOnce(Error) + Send + 'static
+{
+    let compute_data = *compute_data;
+    thread::spawn(move || {
+        match bake_texture(&verts, &indices, (size, size), TEXTURE_PADDING, &compute_data, &cancel, print_progress) {
+            Ok((texture, progress)) => {
+                print_summary(&progress);
+                if progress.is_finished() {
+                    on_finish(&texture);
+                }
+            },
+            Err(e) => on_error(e)
+        }
+        bake_in_progress.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Bakes AO, or other quantity of `compute_data.mode`, of the mesh into a texture of `(width, height)` size using vertex UVs.
+///
+/// Texels not covered by any triangle are filled by growing islands outwards for `padding` pixels
+/// to hide seams when sampling with filtering, the rest is left black.
+pub fn bake_texture<P>(verts: &[Vertex], indices: &[u32], size: (u32, u32), padding: u32, compute_data: &ComputeData, cancel: &CancelToken, on_progress: P) -> Result<(Texture, Progress)>
+    where P: FnMut(&Progress)
+{
+    bake_texture_from((verts, indices), None, size, padding, compute_data, cancel, on_progress)
+}
+
+/// Same as `bake_texture`, but texels are projected onto `projection` high poly mesh like by `bake_projected`
+pub fn bake_texture_projected<P>(mesh: (&[Vertex], &[u32]), projection: &Projection, size: (u32, u32), padding: u32, compute_data: &ComputeData, cancel: &CancelToken, on_progress: P) -> Result<(Texture, Progress)>
+    where P: FnMut(&Progress)
+{
+    check_projection(mesh.0, projection)?;
+    bake_texture_from(mesh, Some(projection), size, padding, compute_data, cancel, on_progress)
+}
+
+fn bake_texture_from<P>((verts, indices): (&[Vertex], &[u32]), projection: Option<&Projection>, (width, height): (u32, u32), padding: u32, compute_data: &ComputeData, cancel: &CancelToken, mut on_progress: P) -> Result<(Texture, Progress)>
+    where P: FnMut(&Progress)
+{
+    compute_data.validate()?;
+    check_size((width, height))?;
+    check_mesh(verts, indices)?;
+    let (texels, pixel_indices, progress) = if let BakeMode::Curvature = compute_data.mode {
+        // curvature of vertices is interpolated across triangles
+        let mut verts = verts.to_vec();
+        let progress = bake_curvature(&mut verts, indices, compute_data);
+        let (texels, pixel_indices) = rasterize(&verts, indices, width, height);
+        on_progress(&progress);
+        (texels, pixel_indices, progress)
+    } else {
+        let (mut texels, pixel_indices) = rasterize(verts, indices, width, height);
+        let scene = Scene::new(occluders(verts, indices, projection), compute_data.accel, compute_data.ground);
+        let missed = projection.map_or(0, |projection| {
+            let cage = rasterize_cage(verts, indices, projection, width, height);
+            project_points(&mut texels, projection, cage.as_deref(), &scene, compute_data.cage_distance)
+        });
+        let mut progress = bake_points(&mut texels, &scene, compute_data, cancel, |progress, _| on_progress(progress))?;
+        progress.missed = missed;
+        (texels, pixel_indices, progress)
+    };
+
+    let mut pixels = vec![0.0; width as usize * height as usize];
+    let mut mask = vec![false; pixels.len()];
+    for (texel, &index) in texels.iter().zip(pixel_indices.iter()) {
+        pixels[index] = texel.color[0];
+        mask[index] = true;
+    }
+
+    let bent_normals = if compute_data.bent_normals && compute_data.mode == BakeMode::Occlusion {
+        let mut bent_normals = vec![Vector3::zero(); pixels.len()];
+        for (texel, &index) in texels.iter().zip(pixel_indices.iter()) {
+            bent_normals[index] = Vector3::from(texel.bent_normal);
+        }
+        dilate(&mut bent_normals, &mut mask.clone(), width as usize, height as usize, padding);
+        Some(bent_normals.into_iter().map(|n| if n.magnitude2() > 0.0 { n.normalize().into() } else { [0.0; 3] }).collect())
+    } else {
+        None
+    };
+    dilate(&mut pixels, &mut mask, width as usize, height as usize, padding);
+
+    Ok((Texture {width, height, pixels, bent_normals}, progress))
+}
+
+/// Fails unless both sides of the texture are positive and at most `MAX_TEXTURE_SIZE`
+pub fn check_size((width, height): (u32, u32)) -> Result<()> {
+    if width == 0 || height == 0 || width.max(height) > MAX_TEXTURE_SIZE {
+        return Err(Error::InvalidSettings(format!("texture size must be between 1 and {}", MAX_TEXTURE_SIZE)));
+    }
+    Ok(())
+}
+
+/// Bakes tangent space normals of `projection` high poly mesh onto the UV layout of the mesh.
+///
+/// Tangents of the mesh come from `tangents`, green channel points along bitangent (`+Y`, OpenGL convention).
+/// Islands are padded like by `bake_texture`, the rest and texels missing high poly mesh are flat.
+/// Texels are projected a row at a time, when `cancel` stops the bake the remaining ones are flat too.
+pub fn bake_normal_map((verts, indices): (&[Vertex], &[u32]), projection: &Projection, (width, height): (u32, u32), padding: u32, compute_data: &ComputeData, cancel: &CancelToken) -> Result<NormalMap> {
+    compute_data.validate()?;
+    check_size((width, height))?;
+    check_mesh(verts, indices)?;
+    check_projection(verts, projection)?;
+    let tangents = tangents(verts, indices);
+    let texels = coverage(verts, indices, width, height);
+    let low: Vec<Vertex> = texels.iter().map(|texel| interpolate(verts, indices, texel, width, height)).collect();
+
+    let mut high = low.clone();
+    let scene = Scene::new(occluders(verts, indices, Some(projection)), compute_data.accel, None);
+    let cage = rasterize_cage(verts, indices, projection, width, height);
+    let row = width.max(1) as usize;
+    let mut missed = 0;
+    for (i, points) in high.chunks_mut(row).enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let cage = cage.as_deref().map(|cage| &cage[i * row..i * row + points.len()]);
+        missed += project_points(points, projection, cage, &scene, compute_data.cage_distance);
+    }
+
+    let mut pixels = vec![Vector3::zero(); width as usize * height as usize];
+    let mut mask = vec![false; pixels.len()];
+    for ((texel, low), high) in texels.iter().zip(&low).zip(&high) {
+        let mut tangent = Vector3::zero();
+        let mut sign = 0.0;
+        for (&[x, y, z, w], &b) in tangents[texel.triangle..texel.triangle + 3].iter().zip(texel.weights.iter()) {
+            tangent += vec3(x, y, z) * b;
+            sign += w * b;
+        }
+        let normal = Vector3::from(low.normal);
+        let tangent = tangent - normal * normal.dot(tangent);
+        let tangent = if tangent.magnitude2() > 0.0 { tangent.normalize() } else { tangent };
+        let bitangent = normal.cross(tangent) * if sign < 0.0 { -1.0 } else { 1.0 };
+
+        let high = Vector3::from(high.normal);
+        pixels[texel.pixel] = vec3(tangent.dot(high), bitangent.dot(high), normal.dot(high));
+        mask[texel.pixel] = true;
+    }
+    dilate(&mut pixels, &mut mask, width as usize, height as usize, padding);
+
+    let flat = [0.0, 0.0, 1.0];
+    let pixels = pixels.into_iter().zip(mask).map(|(n, filled)| {
+        if filled && n.magnitude2() > 0.0 { n.normalize().into() } else { flat }
+    }).collect();
+    Ok(NormalMap { width, height, pixels, missed })
+}
+
+/// Texel center covered by a triangle in UV space
+struct Coverage {
+    pixel: usize,
+    /// index of the first corner of the triangle in mesh indices
+    triangle: usize,
+    /// barycentric coordinates of the texel center
+    weights: [f32; 3]
+}
+
+/// Finds texel centers that fall inside a triangle in UV space, every texel is taken by the first triangle covering it
+fn coverage(verts: &[Vertex], indices: &[u32], width: u32, height: u32) -> Vec<Coverage> {
+    let (w, h) = (width as f32, height as f32);
+    let mut covered = vec![false; width as usize * height as usize];
+    let mut texels = Vec::new();
+
+    for (triangle, tri) in indices.chunks_exact(3).enumerate() {
+        let tri = [&verts[tri[0] as usize], &verts[tri[1] as usize], &verts[tri[2] as usize]];
+        // pixel space, v axis points up while rows go down
+        let p: Vec<[f32; 2]> = tri.iter().map(|vert| [vert.uv[0] * w, (1.0 - vert.uv[1]) * h]).collect();
+
+        let area = edge(p[0], p[1], p[2]);
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let min_x = p.iter().map(|p| p[0]).fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+        let min_y = p.iter().map(|p| p[1]).fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+        let max_x = (p.iter().map(|p| p[0]).fold(f32::MIN, f32::max).ceil() as u32).min(width);
+        let max_y = (p.iter().map(|p| p[1]).fold(f32::MIN, f32::max).ceil() as u32).min(height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let index = y as usize * width as usize + x as usize;
+                if covered[index] {
+                    continue;
+                }
+                let center = [x as f32 + 0.5, y as f32 + 0.5];
+                let b = [edge(p[1], p[2], center) / area, edge(p[2], p[0], center) / area, edge(p[0], p[1], center) / area];
+                if b.iter().any(|&b| b < 0.0) {
+                    continue;
+                }
+                covered[index] = true;
+                texels.push(Coverage { pixel: index, triangle: triangle * 3, weights: b });
+            }
+        }
+    }
+    texels
+}
+
+/// Creates a surface point for every texel center that falls inside a triangle in UV space with
+/// interpolated vertex attributes, returns points together with their pixel indices
+fn rasterize(verts: &[Vertex], indices: &[u32], width: u32, height: u32) -> (Vec<Vertex>, Vec<usize>) {
+    coverage(verts, indices, width, height).iter().map(|texel| (interpolate(verts, indices, texel, width, height), texel.pixel)).unzip()
+}
+
+fn interpolate(verts: &[Vertex], indices: &[u32], texel: &Coverage, width: u32, height: u32) -> Vertex {
+    let mut pos = [0.0; 3];
+    let mut normal = [0.0; 3];
+    let mut color = [0.0; 3];
+    for (&index, &b) in indices[texel.triangle..texel.triangle + 3].iter().zip(texel.weights.iter()) {
+        let vert = &verts[index as usize];
+        for i in 0..3 {
+            pos[i] += vert.pos[i] * b;
+            normal[i] += vert.normal[i] * b;
+            color[i] += vert.color[i] * b;
+        }
+    }
+    let len = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if len > 0.0 {
+        normal = [normal[0] / len, normal[1] / len, normal[2] / len];
+    }
+
+    let (x, y) = (texel.pixel % width as usize, texel.pixel / width as usize);
+    let uv = [(x as f32 + 0.5) / width as f32, 1.0 - (y as f32 + 0.5) / height as f32];
+    Vertex { color, uv, ..Vertex::new(pos, normal) }
+}
+
+/// Cage position of every texel of `rasterize`, found by rasterizing cage with UVs of `verts`
+fn rasterize_cage(verts: &[Vertex], indices: &[u32], projection: &Projection, width: u32, height: u32) -> Option<Vec<Vertex>> {
+    projection.cage.map(|cage| {
+        let cage: Vec<Vertex> = cage.iter().zip(verts).map(|(cage, vert)| Vertex { pos: cage.pos, ..*vert }).collect();
+        rasterize(&cage, indices, width, height).0
+    })
+}
+
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Grows filled areas by one pixel per iteration, new pixels are averages of filled neighbours
+fn dilate<T>(pixels: &mut [T], mask: &mut [bool], width: usize, height: usize, padding: u32)
+    where T: Copy + Add<Output = T> + Mul<f32, Output = T>
+{
+    for _ in 0..padding {
+        let mut grown = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if mask[y * width + x] {
+                    continue;
+                }
+                let mut sum = None;
+                let mut count = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        if mask[ny * width + nx] {
+                            let pixel = pixels[ny * width + nx];
+                            sum = Some(sum.map_or(pixel, |sum| sum + pixel));
+                            count += 1;
+                        }
+                    }
+                }
+                if let Some(sum) = sum {
+                    grown.push((y * width + x, sum * (1.0 / count as f32)));
+                }
+            }
+        }
+        if grown.is_empty() {
+            break;
+        }
+        for (index, value) in grown {
+            pixels[index] = value;
+            mask[index] = true;
+        }
+    }
+}
@@ -11,6 +11,26 @@ pub const UP_VECTOR: Vector3<f32> = vec3(0.0, 1.0, 0.0);
 pub const ANGLE_SPREAD: f32 = 178.0;
 pub const SAMPLES: u32 = 512;
 pub const MAP: [usize; 8] = [2, 1, 2, 1, 2, 2, 0, 0];
+pub const ADAPTIVE_TOLERANCE: f32 = 0.02;
+pub const ADAPTIVE_MIN_SAMPLES: u32 = 32;
+/// decay rate of exponential falloff
+pub const EXP_FALLOFF: f32 = 4.0;
+/// fraction of mesh bounding box diagonal cavity rays reach when ray distance is unlimited
+pub const CAVITY_DISTANCE: f32 = 0.02;
+/// fraction of mesh bounding box diagonal that is the radius of full curvature
+pub const CURVATURE_RADIUS: f32 = 0.05;
+/// fraction of high poly bounding box diagonal projection rays start at in front of baked points without cage
+pub const CAGE_DISTANCE: f32 = 0.02;
+/// sample points of every triangle in flat per-triangle bakes
+pub const TRIANGLE_POINTS: u32 = 16;
+/// vertices processed by one worker task
+pub const CHUNK_SIZE: usize = 1024;
+/// resolution of baked textures
+pub const TEXTURE_SIZE: u32 = 1024;
+/// largest side of baked textures, keeps buffers of every texel within memory
+pub const MAX_TEXTURE_SIZE: u32 = 16384;
+/// pixels islands are grown by in baked textures
+pub const TEXTURE_PADDING: u32 = 4;
 
 /// consts for window management
 pub const SIZE_X: u32 = 1280;
@@ -20,6 +40,7 @@ pub const APP_NAME: &str = "AO Baker";
 
 /// consts for rendering
 pub const FONT_BYTES: &[u8] = include_bytes!("../fonts/Roboto.ttf");
+pub const MESSAGE_COLOR: [f32; 3] = [1.0, 0.35, 0.3];
 pub const QUAD: [VertexUV; 6] = [
     VertexUV{pos: [0.0, 0.0, 0.0], uv: [0.0, 0.0]},
     VertexUV{pos: [1.0, 0.0, 0.0], uv: [1.0, 0.0]},
@@ -28,10 +49,19 @@ pub const QUAD: [VertexUV; 6] = [
     VertexUV{pos: [0.0, 0.0, 0.0], uv: [0.0, 0.0]},
     VertexUV{pos: [1.0, 1.0, 0.0], uv: [1.0, 1.0]}
 ];
-pub const TOOLTIPS: [&str; 5] = [
+pub const TOOLTIPS: [&str; 14] = [
     "P - toggle animation",
     "D - toggle shading",
     "F - toggle AO",
     "Esc - end bake",
-    "G - toggle grid"
+    "G - toggle grid",
+    "S - save output",
+    "E - cycle export format",
+    "T - bake texture",
+    "M - cycle bake target",
+    "K - cycle bake mode",
+    "B - toggle bent normals",
+    "O - toggle ground occluder",
+    "I - cycle image format",
+    "C - cycle packing channel"
 ];
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Error of loading, baking or saving
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Malformed file, `line` is known for text formats
+    Parse {
+        line: Option<usize>,
+        message: String
+    },
+    /// No loader or exporter handles the file
+    UnsupportedFormat(PathBuf),
+    /// Bake settings out of their valid range
+    InvalidSettings(String),
+    /// Valid input holding geometry that can't be baked
+    UnsupportedGeometry(String),
+    /// Input has no triangles
+    EmptyMesh
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn parse<M: fmt::Display>(message: M) -> Self {
+        Error::Parse { line: None, message: message.to_string() }
+    }
+
+    /// Exit code of the CLI, invalid settings are invalid arguments
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidSettings(_) => 2,
+            Error::Io(_) => 3,
+            Error::Parse { .. } => 4,
+            Error::UnsupportedFormat(_) => 5,
+            Error::UnsupportedGeometry(_) => 6,
+            Error::EmptyMesh => 7
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse { line: Some(line), message } => write!(f, "line {}: {}", line, message),
+            Error::Parse { line: None, message } => write!(f, "{}", message),
+            Error::UnsupportedFormat(path) => write!(f, "unsupported format of {:?}", path),
+            Error::InvalidSettings(message) => write!(f, "invalid settings: {}", message),
+            Error::UnsupportedGeometry(message) => write!(f, "unsupported geometry: {}", message),
+            Error::EmptyMesh => write!(f, "no triangles to bake")
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            // message of `e` is already part of ours
+            Error::Io(e) => e.source(),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -2,16 +2,29 @@ use glium::implement_vertex;
 
 use wavefront_obj::obj::{Object, Primitive};
 
-use std::collections::HashMap;
+use cgmath::{Vector3, prelude::*};
+
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::ops::Range;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vertex {
     pub pos: [f32; 3],
     pub color: [f32; 3],
-    pub normal: [f32; 3]
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    /// average unoccluded direction, zero unless baked
+    pub bent_normal: [f32; 3]
 }
 
-implement_vertex!(Vertex, pos, color, normal);
+implement_vertex!(Vertex, pos, color, normal, uv, bent_normal);
+
+impl Vertex {
+    /// White vertex with zero UV and no bent normal
+    pub fn new(pos: [f32; 3], normal: [f32; 3]) -> Self {
+        Vertex { pos, color: [1.0; 3], normal, uv: [0.0; 2], bent_normal: [0.0; 3] }
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct VertexUV {
@@ -21,31 +34,538 @@ pub struct VertexUV {
 
 implement_vertex!(VertexUV, pos, uv);
 
-pub fn generate_vector_buffer(obj: &Object) -> (Vec<Vertex>, Vec<u32>) {
-    let mut verts = Vec::with_capacity(obj.geometry[0].shapes.len() * 3);
+/// Indexed triangle mesh, every mesh loader produces it
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub verts: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub has_uvs: bool,
+    /// Parts the mesh was merged from, empty when it is a single object
+    pub objects: Vec<MeshObject>,
+    /// Problems of the file which didn't stop loading, like ignored primitives
+    pub warnings: Vec<String>
+}
+
+/// Named part of `Mesh`, e.g. OBJ object or group, or glTF mesh
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshObject {
+    pub name: String,
+    /// Range of `Mesh::indices` holding triangles of the object
+    pub indices: Range<usize>
+}
+
+impl Mesh {
+    /// Copy with own vertex for every triangle corner, used by corner and triangle bake targets.
+    /// Triangle order and objects stay the same.
+    pub fn split_corners(&self) -> Mesh {
+        Mesh {
+            verts: self.indices.iter().map(|&i| self.verts[i as usize]).collect(),
+            indices: (0..self.indices.len() as u32).collect(),
+            has_uvs: self.has_uvs,
+            objects: self.objects.clone(),
+            warnings: self.warnings.clone()
+        }
+    }
+
+    /// Appends `other` with its indices and objects offset to follow the current ones
+    pub fn append(&mut self, other: Mesh) {
+        let vert_offset = self.verts.len() as u32;
+        let index_offset = self.indices.len();
+        self.verts.extend(other.verts);
+        self.indices.extend(other.indices.iter().map(|&i| i + vert_offset));
+        self.has_uvs |= other.has_uvs;
+        self.objects.extend(other.objects.into_iter().map(|object| MeshObject {
+            name: object.name,
+            indices: object.indices.start + index_offset..object.indices.end + index_offset
+        }));
+        self.warnings.extend(other.warnings);
+    }
+}
+
+/// Sets normals of vertices to area weighted average of normals of triangles sharing them
+pub fn compute_normals(verts: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::zero(); verts.len()];
+    for tri in indices.chunks_exact(3) {
+        let a = Vector3::from(verts[tri[0] as usize].pos);
+        let b = Vector3::from(verts[tri[1] as usize].pos);
+        let c = Vector3::from(verts[tri[2] as usize].pos);
+        // cross product length is twice the area
+        let normal = (b - a).cross(c - a);
+        for &i in tri {
+            normals[i as usize] += normal;
+        }
+    }
+    for (vert, normal) in verts.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0.0 {
+            vert.normal = normal.normalize().into();
+        }
+    }
+}
+
+/// Sets normals of vertices to angle weighted average of normals of triangles sharing their position.
+///
+/// With `crease_angle` (in degrees) triangles meeting at a sharper angle don't affect each other's normals
+/// and vertices on such hard edges are split.
+pub fn smooth_normals(verts: &mut Vec<Vertex>, indices: &mut [u32], crease_angle: Option<f32>) {
+    let pos_key = |vert: &Vertex| [vert.pos[0].to_bits(), vert.pos[1].to_bits(), vert.pos[2].to_bits()];
+    let mut face_normals = Vec::with_capacity(indices.len() / 3);
+    // triangles around every position with their angle at it
+    let mut around: HashMap<[u32; 3], Vec<(usize, f32)>> = HashMap::new();
+    for (t, tri) in indices.chunks_exact(3).enumerate() {
+        let corners = [0, 1, 2].map(|k| Vector3::from(verts[tri[k] as usize].pos));
+        let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        face_normals.push(if normal.magnitude2() > 0.0 { normal.normalize() } else { normal });
+        for k in 0..3 {
+            let a = corners[(k + 1) % 3] - corners[k];
+            let b = corners[(k + 2) % 3] - corners[k];
+            let angle = if a.magnitude2() > 0.0 && b.magnitude2() > 0.0 { a.angle(b).0 } else { 0.0 };
+            around.entry(pos_key(&verts[tri[k] as usize])).or_default().push((t, angle));
+        }
+    }
+
+    let min_cos = crease_angle.map(|angle| angle.to_radians().cos());
+    let mut is_set = vec![false; verts.len()];
+    let mut copies: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    for (t, tri) in indices.chunks_exact_mut(3).enumerate() {
+        for index in tri {
+            let mut normal = Vector3::zero();
+            for &(other, angle) in &around[&pos_key(&verts[*index as usize])] {
+                if min_cos.is_none_or(|min_cos| face_normals[t].dot(face_normals[other]) >= min_cos) {
+                    normal += face_normals[other] * angle;
+                }
+            }
+            let normal: [f32; 3] = if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else if face_normals[t].magnitude2() > 0.0 {
+                face_normals[t].into()
+            } else {
+                [0.0, 0.0, 1.0]
+            };
+
+            let vert = *index;
+            *index = *copies.entry((vert, normal.map(f32::to_bits))).or_insert_with(|| {
+                if !is_set[vert as usize] {
+                    is_set[vert as usize] = true;
+                    verts[vert as usize].normal = normal;
+                    vert
+                } else {
+                    verts.push(Vertex { normal, ..verts[vert as usize] });
+                    verts.len() as u32 - 1
+                }
+            });
+        }
+    }
+}
+
+/// Mean curvature at every vertex estimated from change of normals along edges to neighbouring positions,
+/// positive where the surface is convex.
+///
+/// Vertices sharing a position are treated as one with their normals averaged, so hard edges and split
+/// meshes get the same result as smooth ones.
+pub fn mean_curvature(verts: &[Vertex], indices: &[u32]) -> Vec<f32> {
+    // adding zero turns negative zero positive, so both land in one group
+    let pos_key = |vert: &Vertex| vert.pos.map(|c| (c + 0.0).to_bits());
+    let mut groups: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut positions = Vec::new();
+    let mut normals: Vec<Vector3<f32>> = Vec::new();
+    let group_of: Vec<usize> = verts.iter().map(|vert| {
+        let group = *groups.entry(pos_key(vert)).or_insert_with(|| {
+            positions.push(Vector3::from(vert.pos));
+            normals.push(Vector3::zero());
+            positions.len() - 1
+        });
+        normals[group] += Vector3::from(vert.normal);
+        group
+    }).collect();
+    for normal in &mut normals {
+        if normal.magnitude2() > 0.0 {
+            *normal = normal.normalize();
+        }
+    }
+
+    // edges shared by two triangles are counted once
+    let mut edges = HashSet::new();
+    for tri in indices.chunks_exact(3) {
+        for k in 0..3 {
+            let (a, b) = (group_of[tri[k] as usize], group_of[tri[(k + 1) % 3] as usize]);
+            if a != b {
+                edges.insert((a.min(b), a.max(b)));
+            }
+        }
+    }
+    let mut sums = vec![(0.0, 0); positions.len()];
+    for (a, b) in edges {
+        let edge = positions[a] - positions[b];
+        if edge.magnitude2() == 0.0 {
+            continue;
+        }
+        // for sphere of radius r every edge gives 1/r
+        let curvature = (normals[a] - normals[b]).dot(edge) / edge.magnitude2();
+        for group in [a, b] {
+            sums[group].0 += curvature;
+            sums[group].1 += 1;
+        }
+    }
+    group_of.iter().map(|&group| match sums[group] {
+        (_, 0) => 0.0,
+        (sum, count) => sum / count as f32
+    }).collect()
+}
+
+/// Tangent of every triangle corner for tangent space normal mapping, one for every index, `w` is handedness
+/// of bitangent `w * normal x tangent`.
+///
+/// Port of MikkTSpace with its default angular threshold: vertices equal in position, normal and UV are welded,
+/// tangents of triangles given by their UV layout are projected onto the tangent plane of the vertex normal and
+/// summed weighted by angle of the triangle at the vertex. Only triangles around the vertex connected by shared
+/// edges and with the same UV handedness are summed together, so corners at mirrored UV seams are split.
+pub fn tangents(verts: &[Vertex], indices: &[u32]) -> Vec<[f32; 4]> {
+    let welded = weld(verts, indices);
+    let mut faces: Vec<FaceTangent> = indices.chunks_exact(3).map(|tri| FaceTangent::new([0, 1, 2].map(|k| &verts[tri[k] as usize]))).collect();
+    let neighbors = face_neighbors(&welded, &faces);
+
+    // corners around a vertex are grouped by flood fill over neighbors with the same handedness
+    let mut groups: Vec<TangentGroup> = Vec::new();
+    let mut assigned = vec![[None; 3]; faces.len()];
+    for face in 0..faces.len() {
+        for k in 0..3 {
+            if faces[face].degenerate || faces[face].any || assigned[face][k].is_some() {
+                continue;
+            }
+            groups.push(TangentGroup { vertex: welded[face * 3 + k], orient: faces[face].orient, faces: Vec::new() });
+            let group = groups.len() - 1;
+            let mut stack = vec![face];
+            while let Some(face) = stack.pop() {
+                let Some(k) = (0..3).find(|&k| welded[face * 3 + k] == groups[group].vertex) else { continue };
+                if assigned[face][k].is_some() {
+                    continue;
+                }
+                // the first group reaching a triangle without UV gradient decides its handedness
+                if faces[face].any && assigned[face].iter().all(Option::is_none) {
+                    faces[face].orient = groups[group].orient;
+                }
+                if faces[face].orient != groups[group].orient {
+                    continue;
+                }
+                groups[group].faces.push(face);
+                assigned[face][k] = Some(group);
+                stack.extend(neighbors[face][k]);
+                stack.extend(neighbors[face][(k + 2) % 3]);
+            }
+        }
+    }
+
+    let mut result: Vec<Option<[f32; 4]>> = vec![None; indices.len()];
+    for group in &groups {
+        // tangent and bitangent directions of members projected onto the tangent plane, with their corner angle
+        let members: Vec<(usize, Vector3<f32>, Vector3<f32>, f32)> = group.faces.iter().map(|&face| {
+            let k = (0..3).find(|&k| welded[face * 3 + k] == group.vertex).unwrap();
+            let corner = |k: usize| &verts[indices[face * 3 + k % 3] as usize];
+            let normal = Vector3::from(corner(k).normal);
+            let project = |v: Vector3<f32>| {
+                let v = v - normal * normal.dot(v);
+                if v.magnitude2() > 0.0 { v.normalize() } else { v }
+            };
+            let pos = Vector3::from(corner(k).pos);
+            let (a, b) = (project(Vector3::from(corner(k + 1).pos) - pos), project(Vector3::from(corner(k + 2).pos) - pos));
+            // triangles without UV gradient only join the group
+            let angle = if faces[face].any { 0.0 } else { a.dot(b).clamp(-1.0, 1.0).acos() };
+            (face * 3 + k, project(faces[face].tangent), project(faces[face].bitangent), angle)
+        }).collect();
+
+        for &(corner, tangent, bitangent, _) in &members {
+            // members whose directions differ by the threshold angle or more would get a tangent of their own
+            let sum = members.iter()
+                .filter(|(_, t, b, _)| tangent.dot(*t) > TANGENT_THRESHOLD_COS && bitangent.dot(*b) > TANGENT_THRESHOLD_COS)
+                .fold(Vector3::zero(), |sum, &(_, t, _, angle)| sum + t * angle);
+            if sum.magnitude2() > 0.0 {
+                let tangent = sum.normalize();
+                result[corner] = Some([tangent.x, tangent.y, tangent.z, if group.orient { 1.0 } else { -1.0 }]);
+            }
+        }
+    }
+
+    // degenerate triangles take tangents of other corners of their vertices
+    let mut by_vertex = HashMap::new();
+    for (corner, tangent) in result.iter().enumerate() {
+        if let Some(tangent) = tangent {
+            by_vertex.entry(welded[corner]).or_insert(*tangent);
+        }
+    }
+    result.iter().enumerate().map(|(corner, tangent)| {
+        tangent.or_else(|| by_vertex.get(&welded[corner]).copied()).unwrap_or_else(|| {
+            // no UV gradient, any direction in the tangent plane will do
+            let normal = Vector3::from(verts[indices[corner] as usize].normal);
+            let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+            let tangent = normal.cross(axis);
+            let tangent = if tangent.magnitude2() > 0.0 { tangent.normalize() } else { Vector3::unit_x() };
+            [tangent.x, tangent.y, tangent.z, 1.0]
+        })
+    }).collect()
+}
+
+/// Cosine of MikkTSpace default angular threshold of 180 degrees, only opposite directions are kept apart
+const TANGENT_THRESHOLD_COS: f32 = -1.0;
+
+/// Tangent frame of a triangle given by its UV layout
+struct FaceTangent {
+    /// normalized direction of growing U, flipped for mirrored UVs
+    tangent: Vector3<f32>,
+    /// normalized direction of growing V, flipped for mirrored UVs
+    bitangent: Vector3<f32>,
+    /// UVs keep winding of the triangle
+    orient: bool,
+    /// no UV gradient, triangle joins tangents of any handedness
+    any: bool,
+    /// corners share a position, tangents come from other triangles
+    degenerate: bool
+}
+
+impl FaceTangent {
+    fn new(corners: [&Vertex; 3]) -> Self {
+        let [p0, p1, p2] = [0, 1, 2].map(|k| Vector3::from(corners[k].pos));
+        let [uv0, uv1, uv2] = [0, 1, 2].map(|k| corners[k].uv);
+        let (d1, d2) = (p1 - p0, p2 - p0);
+        let (t21, t31) = ([uv1[0] - uv0[0], uv1[1] - uv0[1]], [uv2[0] - uv0[0], uv2[1] - uv0[1]]);
+        let area = t21[0] * t31[1] - t21[1] * t31[0];
+        let mut face = FaceTangent {
+            tangent: d1 * t31[1] - d2 * t21[1],
+            bitangent: d2 * t21[0] - d1 * t31[0],
+            orient: area > 0.0,
+            any: true,
+            degenerate: p0 == p1 || p0 == p2 || p1 == p2
+        };
+        if area != 0.0 {
+            let sign = if face.orient { 1.0 } else { -1.0 };
+            let (tangent, bitangent) = (face.tangent.magnitude(), face.bitangent.magnitude());
+            if tangent > 0.0 {
+                face.tangent *= sign / tangent;
+            }
+            if bitangent > 0.0 {
+                face.bitangent *= sign / bitangent;
+            }
+            face.any = tangent == 0.0 || bitangent == 0.0;
+        }
+        face
+    }
+}
+
+/// Triangles around a welded vertex whose corners share one tangent frame
+struct TangentGroup {
+    vertex: u32,
+    orient: bool,
+    faces: Vec<usize>
+}
+
+/// Index of the first vertex equal in position, normal and UV for every index
+fn weld(verts: &[Vertex], indices: &[u32]) -> Vec<u32> {
+    // adding zero turns negative zero positive, so both weld together
+    let key = |vert: &Vertex| {
+        let mut key = [0; 8];
+        for (k, c) in vert.pos.iter().chain(&vert.normal).chain(&vert.uv).enumerate() {
+            key[k] = (c + 0.0).to_bits();
+        }
+        key
+    };
+    let mut first = HashMap::new();
+    indices.iter().map(|&i| *first.entry(key(&verts[i as usize])).or_insert(i)).collect()
+}
+
+/// Triangle across edge from corner `k` to `k + 1` of every triangle, edges of degenerate ones aren't shared
+fn face_neighbors(welded: &[u32], faces: &[FaceTangent]) -> Vec<[Option<usize>; 3]> {
+    let edge = |face: usize, k: usize| (welded[face * 3 + k], welded[face * 3 + (k + 1) % 3]);
+    let mut edges = HashMap::new();
+    for face in (0..faces.len()).filter(|&face| !faces[face].degenerate) {
+        for k in 0..3 {
+            edges.entry(edge(face, k)).or_insert(face);
+        }
+    }
+    (0..faces.len()).map(|face| {
+        let mut neighbors = [None; 3];
+        if !faces[face].degenerate {
+            for (k, neighbor) in neighbors.iter_mut().enumerate() {
+                let (a, b) = edge(face, k);
+                *neighbor = edges.get(&(b, a)).copied();
+            }
+        }
+        neighbors
+    }).collect()
+}
+
+/// Splits polygon into triangles of indices into `polygon` keeping its winding.
+///
+/// Convex polygons are fanned, concave ones are ear clipped in the plane they mostly lie in.
+pub fn triangulate(polygon: &[[f32; 3]]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    let fan = || (1..n.saturating_sub(1)).map(|i| [0, i, i + 1]).collect();
+    if n <= 3 {
+        return fan();
+    }
+
+    // Newell's method gives normal of non planar polygons too
+    let mut normal = Vector3::<f32>::zero();
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % n];
+        normal.x += (a[1] - b[1]) * (a[2] + b[2]);
+        normal.y += (a[2] - b[2]) * (a[0] + b[0]);
+        normal.z += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    let abs = [normal.x.abs(), normal.y.abs(), normal.z.abs()];
+    if abs.iter().all(|&c| c == 0.0) {
+        return fan();
+    }
+    // dropping the dominant axis keeps polygon counterclockwise when that component is positive
+    let (u, v) = if abs[0] >= abs[1] && abs[0] >= abs[2] {
+        if normal.x > 0.0 { (1, 2) } else { (2, 1) }
+    } else if abs[1] >= abs[2] {
+        if normal.y > 0.0 { (2, 0) } else { (0, 2) }
+    } else if normal.z > 0.0 { (0, 1) } else { (1, 0) };
+    let points: Vec<[f32; 2]> = polygon.iter().map(|p| [p[u], p[v]]).collect();
+    let cross = |a: usize, b: usize, c: usize| {
+        let (a, b, c) = (points[a], points[b], points[c]);
+        (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+    };
+
+    if (0..n).all(|i| cross(i, (i + 1) % n, (i + 2) % n) >= 0.0) {
+        return fan();
+    }
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let corner = |i: usize| (remaining[(i + len - 1) % len], remaining[i], remaining[(i + 1) % len]);
+        let is_ear = |i: usize| {
+            let (a, b, c) = corner(i);
+            cross(a, b, c) > 0.0 && remaining.iter().all(|&p| {
+                p == a || p == b || p == c || cross(a, b, p) < 0.0 || cross(b, c, p) < 0.0 || cross(c, a, p) < 0.0
+            })
+        };
+        // self intersecting or degenerate polygons may have no ear, clipping anything keeps progress
+        let ear = (0..len).find(|&i| is_ear(i)).unwrap_or(0);
+        let (a, b, c) = corner(ear);
+        triangles.push([a, b, c]);
+        remaining.remove(ear);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+/// Builds mesh of all geometry of `obj`, every run of shapes sharing a group becomes one `MeshObject`.
+///
+/// When some vertex lacks normal, normals of the whole object are generated by `smooth_normals`.
+pub fn generate_vector_buffer(obj: &Object, crease_angle: Option<f32>) -> Mesh {
+    let generate_normals = obj.geometry.iter().flat_map(|geometry| &geometry.shapes).any(|shape| match shape.primitive {
+        Primitive::Triangle(a, b, c) => a.2.is_none() || b.2.is_none() || c.2.is_none(),
+        _ => false
+    });
+    let shape_count: usize = obj.geometry.iter().map(|geometry| geometry.shapes.len()).sum();
+    let mut verts = Vec::with_capacity(shape_count * 3);
     let mut indices_vec = Vec::new();
+    let mut objects: Vec<MeshObject> = Vec::new();
+    let mut group = None;
     let mut map = HashMap::new();
     let mut i = 0;
-    for shape in &obj.geometry[0].shapes {
+    for shape in obj.geometry.iter().flat_map(|geometry| &geometry.shapes) {
         if let Primitive::Triangle(a, b, c) = shape.primitive {
+            let shape_group = shape.groups.first();
+            if objects.is_empty() || group != shape_group {
+                group = shape_group;
+                objects.push(MeshObject { name: object_name(&obj.name, group), indices: indices_vec.len()..indices_vec.len() });
+            }
             for index in &[a, b, c] {
-                if !map.contains_key(&(index.0, index.2.unwrap())) {
-                    map.insert((index.0, index.2.unwrap()), i);
+                let normal_index = if generate_normals { None } else { index.2 };
+                if let Entry::Vacant(entry) = map.entry((index.0, index.1, normal_index)) {
+                    entry.insert(i);
                     let vert_a = obj.vertices[index.0];
-                    let norm_a = obj.normals[index.2.unwrap()];
-                    let vert = Vertex {
-                        color: [1.0; 3],
-                        pos: [vert_a.x as f32, vert_a.y as f32, vert_a.z as f32],
-                        normal: [norm_a.x as f32, norm_a.y as f32, norm_a.z as f32]
-                    };
+                    let normal = normal_index.map(|n| [obj.normals[n].x as f32, obj.normals[n].y as f32, obj.normals[n].z as f32]).unwrap_or_default();
+                    let uv = index.1.map(|t| [obj.tex_vertices[t].u as f32, obj.tex_vertices[t].v as f32]).unwrap_or_default();
+                    let vert = Vertex { uv, ..Vertex::new([vert_a.x as f32, vert_a.y as f32, vert_a.z as f32], normal) };
                     verts.push(vert);
                     indices_vec.push(i);
                     i += 1;
                 } else {
-                    indices_vec.push(map[&(index.0, index.2.unwrap())]);
+                    indices_vec.push(map[&(index.0, index.1, normal_index)]);
                 }
             }
+            objects.last_mut().unwrap().indices.end = indices_vec.len();
+        }
+    }
+    if generate_normals {
+        smooth_normals(&mut verts, &mut indices_vec, crease_angle);
+    }
+    Mesh { verts, indices: indices_vec, has_uvs: !obj.tex_vertices.is_empty(), objects, warnings: Vec::new() }
+}
+
+/// `object/group`, parts missing or called `default` are left out
+fn object_name(object: &str, group: Option<&String>) -> String {
+    let parts: Vec<&str> = [Some(object), group.map(String::as_str)].iter().flatten()
+        .filter(|part| !part.is_empty() && **part != "default").copied().collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(pos: [f32; 3], uv: [f32; 2]) -> Vertex {
+        Vertex { uv, ..Vertex::new(pos, [0.0, 0.0, 1.0]) }
+    }
+
+    #[test]
+    fn mirrored_uvs_split_tangents() {
+        // quad from x = -1 to 1 with U mirrored at x = 0, seam vertices are shared by both halves
+        let verts = [
+            vertex([-1.0, 0.0, 0.0], [1.0, 0.0]), vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([-1.0, 1.0, 0.0], [1.0, 1.0]), vertex([0.0, 1.0, 0.0], [0.0, 1.0]), vertex([1.0, 1.0, 0.0], [1.0, 1.0])
+        ];
+        let indices = [0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4];
+        let tangents = tangents(&verts, &indices);
+        for (corner, tangent) in tangents.iter().enumerate() {
+            let expected = if corner < 6 { [-1.0, 0.0, 0.0, -1.0] } else { [1.0, 0.0, 0.0, 1.0] };
+            for (a, b) in tangent.iter().zip(expected) {
+                assert!((a - b).abs() < 1e-5, "corner {}: {:?} instead of {:?}", corner, tangent, expected);
+            }
+        }
+    }
+
+    /// Cube from -1 to 1 with outward winding, vertex `i` has bits of `i` as its coordinates
+    fn cube() -> (Vec<Vertex>, Vec<u32>) {
+        let bit = |i: u32, b: u32| if i & b == 0 { -1.0 } else { 1.0 };
+        let verts = (0..8).map(|i| Vertex::new([bit(i, 1), bit(i, 2), bit(i, 4)], [0.0; 3])).collect();
+        let quads = [[0, 4, 6, 2], [1, 3, 7, 5], [0, 1, 5, 4], [2, 6, 7, 3], [0, 2, 3, 1], [4, 5, 7, 6]];
+        let indices = quads.iter().flat_map(|&[a, b, c, d]| [a, b, c, a, c, d]).collect();
+        (verts, indices)
+    }
+
+    #[test]
+    fn smooth_normals_split_creases() {
+        // every corner touches one triangle of some faces and two of others, angle weighting evens them out
+        let (mut verts, mut indices) = cube();
+        smooth_normals(&mut verts, &mut indices, None);
+        assert_eq!(verts.len(), 8);
+        for vert in &verts {
+            let expected = Vector3::from(vert.pos).normalize();
+            assert!((Vector3::from(vert.normal) - expected).magnitude() < 1e-5, "{:?}", vert);
+        }
+
+        let (mut verts, mut indices) = cube();
+        smooth_normals(&mut verts, &mut indices, Some(60.0));
+        assert_eq!(verts.len(), 24);
+        for tri in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| Vector3::from(verts[tri[k] as usize].pos));
+            let face = (b - a).cross(c - a).normalize();
+            for &i in tri {
+                assert!((Vector3::from(verts[i as usize].normal) - face).magnitude() < 1e-5, "{:?}", verts[i as usize]);
+            }
         }
     }
-    (verts, indices_vec)
-}
\ No newline at end of file
+
+    #[test]
+    fn smooth_tangents_are_shared() {
+        // two triangles of a unit quad, corners of one vertex get the same tangent
+        let verts = [vertex([0.0, 0.0, 0.0], [0.0, 0.0]), vertex([1.0, 0.0, 0.0], [1.0, 0.0]), vertex([1.0, 1.0, 0.0], [1.0, 1.0]), vertex([0.0, 1.0, 0.0], [0.0, 1.0])];
+        let tangents = tangents(&verts, &[0, 1, 2, 0, 2, 3]);
+        assert!(tangents.iter().all(|&tangent| tangent == [1.0, 0.0, 0.0, 1.0]), "{:?}", tangents);
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::Path;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+use std::ops::Range;
+
+use crate::error::Result;
+use crate::geo::{MeshObject, Vertex};
+
+mod gltf;
+
+/// glTF vertex attribute of baked bent normals
+const BENT_NORMAL_ATTRIBUTE: &str = "_BENT_NORMAL";
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ExportFormat {
+    Obj,
+    PlyAscii,
+    #[default]
+    PlyBinary,
+    /// binary glTF, AO is stored in given vertex attribute
+    Glb(AoAttribute)
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Obj => "obj",
+            ExportFormat::PlyAscii | ExportFormat::PlyBinary => "ply",
+            ExportFormat::Glb(_) => "glb"
+        }
+    }
+
+    /// next format, used for cycling through formats in window
+    pub fn next(self) -> Self {
+        match self {
+            ExportFormat::Obj => ExportFormat::PlyAscii,
+            ExportFormat::PlyAscii => ExportFormat::PlyBinary,
+            ExportFormat::PlyBinary => ExportFormat::Glb(AoAttribute::Color),
+            ExportFormat::Glb(AoAttribute::Color) => ExportFormat::Glb(AoAttribute::Custom),
+            ExportFormat::Glb(AoAttribute::Custom) => ExportFormat::Obj
+        }
+    }
+}
+
+/// glTF vertex attribute holding AO
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum AoAttribute {
+    /// `COLOR_0` with AO in every channel
+    #[default]
+    Color,
+    /// scalar `_AO` application specific attribute
+    Custom
+}
+
+impl AoAttribute {
+    pub fn name(self) -> &'static str {
+        match self {
+            AoAttribute::Color => "COLOR_0",
+            AoAttribute::Custom => "_AO"
+        }
+    }
+}
+
+impl FromStr for AoAttribute {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "color" => Ok(AoAttribute::Color),
+            "ao" => Ok(AoAttribute::Custom),
+            _ => Err(format!("unknown glTF attribute `{}`", s))
+        }
+    }
+}
+
+/// Writes baked mesh with the vertex/index layout of loaded `Mesh`, OBJ and glTF keep `objects` separate
+pub fn export_mesh(path: &Path, format: ExportFormat, verts: &[Vertex], indices: &[u32], objects: &[MeshObject]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let objects = object_ranges(objects, indices.len());
+    match format {
+        ExportFormat::Obj => write_obj(&mut writer, verts, indices, &objects)?,
+        ExportFormat::PlyAscii => write_ply(&mut writer, verts, indices, false)?,
+        ExportFormat::PlyBinary => write_ply(&mut writer, verts, indices, true)?,
+        ExportFormat::Glb(attribute) => gltf::write_glb(&mut writer, attribute, verts, indices, &objects)?
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Same as `export_mesh`, but glTF `input` exported as glTF keeps all its data and only gets AO attribute
+/// added to its primitives. `verts` have to come from loading `input` then.
+pub fn export_baked(path: &Path, format: ExportFormat, input: &Path, verts: &[Vertex], indices: &[u32], objects: &[MeshObject]) -> Result<()> {
+    let input_ext = input.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    match (format, input_ext.as_deref()) {
+        (ExportFormat::Glb(attribute), Some("gltf")) | (ExportFormat::Glb(attribute), Some("glb")) => {
+            let glb = gltf::roundtrip_glb(input, attribute, verts)?;
+            let mut writer = BufWriter::new(File::create(path)?);
+            writer.write_all(&glb)?;
+            writer.flush()?;
+            Ok(())
+        },
+        _ => export_mesh(path, format, verts, indices, objects)
+    }
+}
+
+/// Name and index range of every object, mesh without objects is one unnamed object
+fn object_ranges(objects: &[MeshObject], index_count: usize) -> Vec<(&str, Range<usize>)> {
+    if objects.is_empty() {
+        return vec![("", 0..index_count)];
+    }
+    objects.iter().map(|object| (object.name.as_str(), object.indices.clone())).collect()
+}
+
+/// Bent normals are zero unless they were baked
+fn has_bent_normals(verts: &[Vertex]) -> bool {
+    verts.iter().any(|vert| vert.bent_normal != [0.0; 3])
+}
+
+/// Writes OBJ with `v x y z r g b` vertex color extension, bent normals have no place in it
+fn write_obj<W: Write>(writer: &mut W, verts: &[Vertex], indices: &[u32], objects: &[(&str, Range<usize>)]) -> io::Result<()> {
+    writeln!(writer, "# baked by {}", env!("CARGO_PKG_NAME"))?;
+    for vert in verts {
+        writeln!(writer, "v {} {} {} {} {} {}", vert.pos[0], vert.pos[1], vert.pos[2], vert.color[0], vert.color[1], vert.color[2])?;
+    }
+    for vert in verts {
+        writeln!(writer, "vn {} {} {}", vert.normal[0], vert.normal[1], vert.normal[2])?;
+    }
+    for (name, range) in objects {
+        if !name.is_empty() {
+            writeln!(writer, "o {}", name)?;
+        }
+        for tri in indices[range.clone()].chunks_exact(3) {
+            let (a, b, c) = (tri[0] + 1, tri[1] + 1, tri[2] + 1);
+            writeln!(writer, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes PLY with per-vertex `ao` scalar property, and `bnx bny bnz` when bent normals were baked
+fn write_ply<W: Write>(writer: &mut W, verts: &[Vertex], indices: &[u32], binary: bool) -> io::Result<()> {
+    let format = if binary { "binary_little_endian" } else { "ascii" };
+    let bent_normals = has_bent_normals(verts);
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", format)?;
+    writeln!(writer, "comment baked by {}", env!("CARGO_PKG_NAME"))?;
+    writeln!(writer, "element vertex {}", verts.len())?;
+    for prop in &["x", "y", "z", "nx", "ny", "nz", "ao"] {
+        writeln!(writer, "property float {}", prop)?;
+    }
+    if bent_normals {
+        for prop in &["bnx", "bny", "bnz"] {
+            writeln!(writer, "property float {}", prop)?;
+        }
+    }
+    writeln!(writer, "element face {}", indices.len() / 3)?;
+    writeln!(writer, "property list uchar uint vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    for vert in verts {
+        let mut values = vec![vert.pos[0], vert.pos[1], vert.pos[2], vert.normal[0], vert.normal[1], vert.normal[2], vert.color[0]];
+        if bent_normals {
+            values.extend_from_slice(&vert.bent_normal);
+        }
+        if binary {
+            for value in &values {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        } else {
+            let line: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            writeln!(writer, "{}", line.join(" "))?;
+        }
+    }
+    for tri in indices.chunks_exact(3) {
+        if binary {
+            writer.write_all(&[3])?;
+            for index in tri {
+                writer.write_all(&index.to_le_bytes())?;
+            }
+        } else {
+            writeln!(writer, "3 {} {} {}", tri[0], tri[1], tri[2])?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,358 @@
+use std::path::Path;
+use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::io::Write;
+
+use ::gltf::{Gltf, Glb};
+use serde_json::{json, Value};
+
+use crate::geo::Vertex;
+use crate::error::{Error, Result};
+use crate::io::load::{primitive_instances, read_buffers};
+use super::{has_bent_normals, AoAttribute, BENT_NORMAL_ATTRIBUTE};
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Writes baked mesh as .glb with node and mesh per object. Objects get their own range of vertex buffers
+/// shared by all of them, vertices used by more objects are repeated in each.
+pub fn write_glb<W: Write>(writer: &mut W, attribute: AoAttribute, verts: &[Vertex], indices: &[u32], objects: &[(&str, Range<usize>)]) -> Result<()> {
+    let mut bin = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+
+    // vertices of every object keep their order, primitive indices are local to them
+    let object_verts: Vec<Vec<u32>> = objects.iter().map(|(_, range)| {
+        let mut used = indices[range.clone()].to_vec();
+        used.sort_unstable();
+        used.dedup();
+        used
+    }).collect();
+    let ordered: Vec<&Vertex> = object_verts.iter().flatten().map(|&i| &verts[i as usize]).collect();
+
+    let position_view = view(&mut bin, &mut views, &floats(ordered.iter().flat_map(|vert| vert.pos.iter())), ARRAY_BUFFER);
+    let normal_view = view(&mut bin, &mut views, &floats(ordered.iter().flat_map(|vert| vert.normal.iter())), ARRAY_BUFFER);
+    let ao: Vec<f32> = ordered.iter().map(|vert| vert.color[0]).collect();
+    let (ao_data, ao_kind) = ao_data(attribute, &ao);
+    let ao_view = view(&mut bin, &mut views, &ao_data, ARRAY_BUFFER);
+    let bent_normal_view = if has_bent_normals(verts) {
+        Some(view(&mut bin, &mut views, &floats(ordered.iter().flat_map(|vert| vert.bent_normal.iter())), ARRAY_BUFFER))
+    } else {
+        None
+    };
+
+    let mut nodes = Vec::new();
+    let mut meshes = Vec::new();
+    let mut first = 0;
+    for ((name, range), used) in objects.iter().zip(&object_verts) {
+        let count = used.len();
+        let vertex_accessor = |view: usize, kind: &str| {
+            let size = if kind == "VEC3" { 12 } else { 4 };
+            json!({"bufferView": view, "byteOffset": first * size, "componentType": FLOAT, "count": count, "type": kind})
+        };
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for vert in &ordered[first..first + count] {
+            for i in 0..3 {
+                min[i] = min[i].min(vert.pos[i]);
+                max[i] = max[i].max(vert.pos[i]);
+            }
+        }
+        let mut position = vertex_accessor(position_view, "VEC3");
+        position["min"] = json!(min);
+        position["max"] = json!(max);
+
+        let mut attributes = json!({"POSITION": accessors.len(), "NORMAL": accessors.len() + 1});
+        attributes[attribute.name()] = json!(accessors.len() + 2);
+        accessors.push(position);
+        accessors.push(vertex_accessor(normal_view, "VEC3"));
+        accessors.push(vertex_accessor(ao_view, ao_kind));
+        if let Some(bent_normal_view) = bent_normal_view {
+            attributes[BENT_NORMAL_ATTRIBUTE] = json!(accessors.len());
+            accessors.push(vertex_accessor(bent_normal_view, "VEC3"));
+        }
+
+        let local: HashMap<u32, u32> = used.iter().enumerate().map(|(local, &i)| (i, local as u32)).collect();
+        let index_data: Vec<u8> = indices[range.clone()].iter().flat_map(|i| local[i].to_le_bytes().to_vec()).collect();
+        accessors.push(accessor(&mut bin, &mut views, &index_data, ELEMENT_ARRAY_BUFFER, UNSIGNED_INT, range.len(), "SCALAR"));
+        first += count;
+
+        let mut mesh = json!({"primitives": [{"attributes": attributes, "indices": accessors.len() - 1}]});
+        let mut node = json!({"mesh": meshes.len()});
+        if !name.is_empty() {
+            mesh["name"] = json!(name);
+            node["name"] = json!(name);
+        }
+        meshes.push(mesh);
+        nodes.push(node);
+    }
+
+    let root = json!({
+        "asset": {"version": "2.0", "generator": env!("CARGO_PKG_NAME")},
+        "scene": 0,
+        "scenes": [{"nodes": (0..nodes.len()).collect::<Vec<_>>()}],
+        "nodes": nodes,
+        "meshes": meshes,
+        "accessors": accessors,
+        "bufferViews": views,
+        "buffers": [{"byteLength": bin.len()}]
+    });
+    write_container(writer, &root, &bin)
+}
+
+/// Returns .glb made from glTF file at `source` with AO attribute, and `_BENT_NORMAL` when baked,
+/// added to its triangle primitives.
+///
+/// Everything else is kept as is, only buffers and image files are merged into the binary chunk, so the
+/// output doesn't depend on files next to `source`. Primitives used by more nodes get AO of the first one.
+pub fn roundtrip_glb(source: &Path, attribute: AoAttribute, verts: &[Vertex]) -> Result<Vec<u8>> {
+    let bytes = fs::read(source)?;
+    let gltf = Gltf::from_slice(&bytes).map_err(Error::parse)?;
+    let buffers = read_buffers(&gltf, source)?;
+    // the input was reported when it was loaded
+    let instances = primitive_instances(&gltf, &mut Vec::new())?;
+    if instances.iter().map(|instance| instance.vertex_count).sum::<usize>() != verts.len() {
+        return Err(Error::UnsupportedGeometry(format!("baked mesh doesn't match {:?}", source)));
+    }
+
+    // edited as plain JSON so extensions and extras survive
+    let mut root: Value = if bytes.starts_with(b"glTF") {
+        serde_json::from_slice(&Glb::from_slice(&bytes).map_err(Error::parse)?.json)
+    } else {
+        serde_json::from_slice(&bytes)
+    }.map_err(Error::parse)?;
+
+    let mut bin = Vec::new();
+    let mut bases = Vec::new();
+    for buffer in &buffers {
+        bases.push(bin.len());
+        bin.extend_from_slice(buffer);
+        pad(&mut bin, 0);
+    }
+    let mut views = root["bufferViews"].as_array().cloned().unwrap_or_default();
+    for view in &mut views {
+        let buffer = view["buffer"].as_u64().unwrap_or(0) as usize;
+        let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+        view["buffer"] = json!(0);
+        view["byteOffset"] = json!(bases[buffer] + offset);
+    }
+
+    // indexing would insert null images into files without them
+    if let Some(images) = root.get_mut("images").and_then(Value::as_array_mut) {
+        for image in images.iter_mut().filter_map(Value::as_object_mut) {
+            let uri = match image.get("uri").and_then(Value::as_str) {
+                Some(uri) if !uri.starts_with("data:") => uri.to_string(),
+                _ => continue
+            };
+            let mime_type = match Path::new(&uri).extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+                Some("png") => "image/png",
+                Some("jpg") | Some("jpeg") => "image/jpeg",
+                _ => continue
+            };
+            let data = fs::read(source.with_file_name(&uri))?;
+            views.push(json!({"buffer": 0, "byteOffset": bin.len(), "byteLength": data.len()}));
+            bin.extend_from_slice(&data);
+            pad(&mut bin, 0);
+            image.remove("uri");
+            image.insert("bufferView".to_string(), json!(views.len() - 1));
+            image.insert("mimeType".to_string(), json!(mime_type));
+        }
+    }
+
+    let mut accessors = root["accessors"].as_array().cloned().unwrap_or_default();
+    let mut baked = HashSet::new();
+    let mut offset = 0;
+    let bent_normals = has_bent_normals(verts);
+    for instance in instances {
+        let instance_verts = &verts[offset..offset + instance.vertex_count];
+        offset += instance.vertex_count;
+        if !baked.insert((instance.mesh, instance.primitive)) {
+            continue;
+        }
+        let ao: Vec<f32> = instance_verts.iter().map(|vert| vert.color[0]).collect();
+        let primitive = &mut root["meshes"][instance.mesh]["primitives"][instance.primitive]["attributes"];
+        primitive[attribute.name()] = json!(accessors.len());
+        accessors.push(ao_accessor(&mut bin, &mut views, attribute, &ao));
+        if bent_normals {
+            primitive[BENT_NORMAL_ATTRIBUTE] = json!(accessors.len());
+            let data = floats(instance_verts.iter().flat_map(|vert| vert.bent_normal.iter()));
+            accessors.push(accessor(&mut bin, &mut views, &data, ARRAY_BUFFER, FLOAT, instance_verts.len(), "VEC3"));
+        }
+    }
+
+    root["accessors"] = Value::Array(accessors);
+    root["bufferViews"] = Value::Array(views);
+    root["buffers"] = json!([{"byteLength": bin.len()}]);
+
+    let mut glb = Vec::new();
+    write_container(&mut glb, &root, &bin)?;
+    Ok(glb)
+}
+
+fn ao_accessor(bin: &mut Vec<u8>, views: &mut Vec<Value>, attribute: AoAttribute, ao: &[f32]) -> Value {
+    let (data, kind) = ao_data(attribute, ao);
+    accessor(bin, views, &data, ARRAY_BUFFER, FLOAT, ao.len(), kind)
+}
+
+/// Bytes and accessor type of AO stored in `attribute`
+fn ao_data(attribute: AoAttribute, ao: &[f32]) -> (Vec<u8>, &'static str) {
+    match attribute {
+        AoAttribute::Color => (floats(ao.iter().flat_map(|ao| vec![ao; 3])), "VEC3"),
+        AoAttribute::Custom => (floats(ao.iter()), "SCALAR")
+    }
+}
+
+/// Appends `data` to `bin` as new buffer view, returns accessor of it
+fn accessor(bin: &mut Vec<u8>, views: &mut Vec<Value>, data: &[u8], target: u32, component_type: u32, count: usize, kind: &str) -> Value {
+    let view = view(bin, views, data, target);
+    json!({"bufferView": view, "componentType": component_type, "count": count, "type": kind})
+}
+
+/// Appends `data` to `bin` as new buffer view, returns its index
+fn view(bin: &mut Vec<u8>, views: &mut Vec<Value>, data: &[u8], target: u32) -> usize {
+    views.push(json!({"buffer": 0, "byteOffset": bin.len(), "byteLength": data.len(), "target": target}));
+    bin.extend_from_slice(data);
+    pad(bin, 0);
+    views.len() - 1
+}
+
+fn floats<'a, I: Iterator<Item = &'a f32>>(values: I) -> Vec<u8> {
+    values.flat_map(|value| value.to_le_bytes().to_vec()).collect()
+}
+
+/// Chunks have to be 4 byte aligned
+fn pad(data: &mut Vec<u8>, byte: u8) {
+    let padding = (4 - data.len() % 4) % 4;
+    data.resize(data.len() + padding, byte);
+}
+
+fn write_container<W: Write>(writer: &mut W, root: &Value, bin: &[u8]) -> Result<()> {
+    let mut json = serde_json::to_vec(root).map_err(Error::parse)?;
+    pad(&mut json, b' ');
+    let mut bin = bin.to_vec();
+    pad(&mut bin, 0);
+
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(length as u32).to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json)?;
+    writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+    writer.write_all(b"BIN\0")?;
+    writer.write_all(&bin)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::load::{GltfLoader, MeshLoader};
+
+    /// Two unit squares next to each other as separate objects
+    fn squares() -> (Vec<Vertex>, Vec<u32>) {
+        let verts = (0..8).map(|i| Vertex {
+            color: [i as f32 / 8.0; 3],
+            ..Vertex::new([(i / 4 * 2 + (i % 4 == 1 || i % 4 == 2) as u32) as f32, (i % 4 / 2) as f32, 0.0], [0.0, 0.0, 1.0])
+        }).collect();
+        (verts, vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7])
+    }
+
+    #[test]
+    fn objects_keep_their_vertices() {
+        let (verts, indices) = squares();
+        let mut glb = Vec::new();
+        write_glb(&mut glb, AoAttribute::Custom, &verts, &indices, &[("a", 0..6), ("b", 6..12)]).unwrap();
+
+        let mesh = GltfLoader.load(Path::new("squares.glb"), &glb).unwrap();
+        assert_eq!(mesh.verts.len(), verts.len());
+        assert_eq!(mesh.indices, indices);
+        assert!(mesh.verts.iter().zip(&verts).all(|(loaded, vert)| loaded.pos == vert.pos));
+        assert_eq!(mesh.objects.iter().map(|object| object.name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    /// JSON and floats of `attribute` of the first primitive of `mesh` in .glb
+    fn attribute(glb: &[u8], mesh: usize, attribute: &str) -> (Value, Vec<f32>) {
+        let glb = Glb::from_slice(glb).unwrap();
+        let root: Value = serde_json::from_slice(&glb.json).unwrap();
+        let index = root["meshes"][mesh]["primitives"][0]["attributes"][attribute].as_u64().unwrap() as usize;
+        let accessor = &root["accessors"][index];
+        let view = &root["bufferViews"][accessor["bufferView"].as_u64().unwrap() as usize];
+        let start = (view["byteOffset"].as_u64().unwrap_or(0) + accessor["byteOffset"].as_u64().unwrap_or(0)) as usize;
+        let components = if accessor["type"] == "VEC3" { 3 } else { 1 };
+        let bin = glb.bin.unwrap();
+        let floats = (0..accessor["count"].as_u64().unwrap() as usize * components).map(|i| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&bin[start + i * 4..start + i * 4 + 4]);
+            f32::from_le_bytes(bytes)
+        }).collect();
+        (root, floats)
+    }
+
+    #[test]
+    fn roundtrip_keeps_vertex_count() {
+        let (verts, indices) = squares();
+        let path = std::env::temp_dir().join(format!("ao-baker-roundtrip-{}.glb", std::process::id()));
+        let mut glb = Vec::new();
+        write_glb(&mut glb, AoAttribute::Color, &verts, &indices, &[("a", 0..6), ("b", 6..12)]).unwrap();
+        fs::write(&path, &glb).unwrap();
+        let baked: Vec<Vertex> = verts.iter().map(|vert| Vertex { color: [1.0 - vert.color[0]; 3], ..*vert }).collect();
+        let result = roundtrip_glb(&path, AoAttribute::Custom, &baked);
+        fs::remove_file(&path).unwrap();
+        let result = result.unwrap();
+
+        let mesh = GltfLoader.load(&path, &result).unwrap();
+        assert_eq!(mesh.verts.len(), verts.len());
+        assert_eq!(mesh.indices, indices);
+        assert_eq!(mesh.objects.iter().map(|object| object.name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        for (object, object_verts) in [&verts[..4], &verts[4..]].iter().enumerate() {
+            let (_, ao) = attribute(&result, object, "_AO");
+            assert_eq!(ao, object_verts.iter().map(|vert| 1.0 - vert.color[0]).collect::<Vec<_>>());
+            let (_, color) = attribute(&result, object, "COLOR_0");
+            assert_eq!(color, object_verts.iter().flat_map(|vert| vert.color.to_vec()).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn roundtrip_embeds_relative_images() {
+        let dir = std::env::temp_dir().join(format!("ao-baker-images-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let bin = floats([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter());
+        let image = b"\x89PNG not really".to_vec();
+        fs::write(dir.join("tri.bin"), &bin).unwrap();
+        fs::write(dir.join("tex.png"), &image).unwrap();
+        let json = json!({
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "material": 0}]}],
+            "materials": [{"pbrMetallicRoughness": {"baseColorTexture": {"index": 0}}}],
+            "textures": [{"source": 0}],
+            "images": [{"uri": "tex.png", "name": "tex"}],
+            "accessors": [{"bufferView": 0, "componentType": FLOAT, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}],
+            "bufferViews": [{"buffer": 0, "byteLength": bin.len()}],
+            "buffers": [{"uri": "tri.bin", "byteLength": bin.len()}]
+        });
+        let path = dir.join("tri.gltf");
+        fs::write(&path, serde_json::to_vec(&json).unwrap()).unwrap();
+        let verts = vec![Vertex::new([0.0; 3], [0.0, 0.0, 1.0]); 3];
+        let result = roundtrip_glb(&path, AoAttribute::Custom, &verts);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let glb = result.unwrap();
+        let (root, _) = attribute(&glb, 0, "_AO");
+        let embedded = &root["images"][0];
+        assert_eq!(embedded["mimeType"], "image/png");
+        assert_eq!(embedded["name"], "tex");
+        assert!(embedded.get("uri").is_none());
+        assert!(root["buffers"][0].get("uri").is_none());
+        let view = &root["bufferViews"][embedded["bufferView"].as_u64().unwrap() as usize];
+        let start = view["byteOffset"].as_u64().unwrap() as usize;
+        assert_eq!(&Glb::from_slice(&glb).unwrap().bin.unwrap()[start..start + image.len()], &image[..]);
+    }
+}
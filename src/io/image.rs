@@ -0,0 +1,214 @@
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+use crate::compute::texture::Texture;
+use crate::error::{Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ImageFormat {
+    #[default]
+    Png8,
+    Png16,
+    /// 32-bit float Portable Float Map
+    Pfm
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png8 | ImageFormat::Png16 => "png",
+            ImageFormat::Pfm => "pfm"
+        }
+    }
+
+    /// next format, used for cycling through formats in window
+    pub fn next(self) -> Self {
+        match self {
+            ImageFormat::Png8 => ImageFormat::Png16,
+            ImageFormat::Png16 => ImageFormat::Pfm,
+            ImageFormat::Pfm => ImageFormat::Png8
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "png8" => Ok(ImageFormat::Png8),
+            "png16" => Ok(ImageFormat::Png16),
+            "pfm" => Ok(ImageFormat::Pfm),
+            _ => Err(format!("unknown image format `{}`", s))
+        }
+    }
+}
+
+/// Channel of RGBA image
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Channel {
+    #[default]
+    R,
+    G,
+    B,
+    A
+}
+
+impl Channel {
+    fn index(self) -> usize {
+        match self {
+            Channel::R => 0,
+            Channel::G => 1,
+            Channel::B => 2,
+            Channel::A => 3
+        }
+    }
+
+    /// next channel, used for cycling through channels in window
+    pub fn next(self) -> Self {
+        match self {
+            Channel::R => Channel::G,
+            Channel::G => Channel::B,
+            Channel::B => Channel::A,
+            Channel::A => Channel::R
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "r" => Ok(Channel::R),
+            "g" => Ok(Channel::G),
+            "b" => Ok(Channel::B),
+            "a" => Ok(Channel::A),
+            _ => Err(format!("unknown channel `{}`", s))
+        }
+    }
+}
+
+/// Existing PNG texture AO gets written into, e.g. occlusion channel of ORM texture
+#[derive(Clone, Debug)]
+pub struct Packing {
+    pub target: PathBuf,
+    pub channel: Channel
+}
+
+/// Writes baked texture as grayscale image
+pub fn save_texture(path: &Path, format: ImageFormat, texture: &Texture) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Png8 => {
+            let data: Vec<u8> = texture.pixels.iter().map(|&ao| to_u8(ao)).collect();
+            write_png(&mut writer, texture.width, texture.height, png::ColorType::Grayscale, png::BitDepth::Eight, &data)?;
+        },
+        ImageFormat::Png16 => {
+            let data: Vec<u8> = texture.pixels.iter().flat_map(|&ao| to_u16(ao).to_be_bytes().to_vec()).collect();
+            write_png(&mut writer, texture.width, texture.height, png::ColorType::Grayscale, png::BitDepth::Sixteen, &data)?;
+        },
+        ImageFormat::Pfm => write_pfm(&mut writer, texture)?
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes copy of `packing.target` with baked texture in `packing.channel` to `path`.
+/// Target has to match texture size, it's converted to RGBA keeping its bit depth.
+pub fn pack_texture(path: &Path, packing: &Packing, texture: &Texture) -> Result<()> {
+    let mut decoder = png::Decoder::new(File::open(&packing.target)?);
+    // default transformations strip 16-bit samples
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info().map_err(Error::parse)?;
+    if info.width != texture.width || info.height != texture.height {
+        return Err(Error::InvalidSettings(format!("{:?} is {}x{}, texture is {}x{}",
+            packing.target, info.width, info.height, texture.width, texture.height)));
+    }
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).map_err(Error::parse)?;
+
+    let bytes = match info.bit_depth {
+        png::BitDepth::Sixteen => 2,
+        _ => 1
+    };
+    let mut rgba = to_rgba(&buf, info.color_type, bytes);
+    let channel = packing.channel.index();
+    for (pixel, &ao) in rgba.chunks_exact_mut(4 * bytes).zip(texture.pixels.iter()) {
+        if bytes == 2 {
+            pixel[channel * 2..channel * 2 + 2].copy_from_slice(&to_u16(ao).to_be_bytes());
+        } else {
+            pixel[channel] = to_u8(ao);
+        }
+    }
+
+    let depth = if bytes == 2 { png::BitDepth::Sixteen } else { png::BitDepth::Eight };
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_png(&mut writer, texture.width, texture.height, png::ColorType::RGBA, depth, &rgba)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes unit vectors as 8-bit RGB image mapping `-1..=1` to `0..=255`, zero vectors become flat gray
+pub fn save_normal_map(path: &Path, width: u32, height: u32, normals: &[[f32; 3]]) -> Result<()> {
+    let data: Vec<u8> = normals.iter().flat_map(|normal| normal.iter().map(|&n| to_u8(n * 0.5 + 0.5))).collect();
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_png(&mut writer, width, height, png::ColorType::RGB, png::BitDepth::Eight, &data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_png<W: Write>(writer: W, width: u32, height: u32, color: png::ColorType, depth: png::BitDepth, data: &[u8]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    encoder.write_header()?.write_image_data(data)?;
+    Ok(())
+}
+
+/// PFM stores rows bottom to top, negative scale marks little endian
+fn write_pfm<W: Write>(writer: &mut W, texture: &Texture) -> io::Result<()> {
+    write!(writer, "Pf\n{} {}\n-1.0\n", texture.width, texture.height)?;
+    for row in texture.pixels.chunks_exact(texture.width as usize).rev() {
+        for ao in row {
+            writer.write_all(&ao.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Expands decoded samples of `color` type to RGBA, `bytes` per sample are kept
+fn to_rgba(buf: &[u8], color: png::ColorType, bytes: usize) -> Vec<u8> {
+    let channels = match color {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA | png::ColorType::Indexed => 4
+    };
+    let opaque = vec![0xff; bytes];
+    let mut rgba = Vec::with_capacity(buf.len() / channels * 4);
+    for pixel in buf.chunks_exact(channels * bytes) {
+        let sample = |i: usize| &pixel[i * bytes..(i + 1) * bytes];
+        let (rgb, alpha) = match channels {
+            1 => ([sample(0), sample(0), sample(0)], &opaque[..]),
+            2 => ([sample(0), sample(0), sample(0)], sample(1)),
+            3 => ([sample(0), sample(1), sample(2)], &opaque[..]),
+            _ => ([sample(0), sample(1), sample(2)], sample(3))
+        };
+        for channel in rgb.iter() {
+            rgba.extend_from_slice(channel);
+        }
+        rgba.extend_from_slice(alpha);
+    }
+    rgba
+}
+
+fn to_u8(ao: f32) -> u8 {
+    (ao.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn to_u16(ao: f32) -> u16 {
+    (ao.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
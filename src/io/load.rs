@@ -0,0 +1,85 @@
+use std::path::Path;
+use std::fs;
+
+use crate::error::{Error, Result};
+use crate::geo::Mesh;
+
+mod obj;
+mod ply;
+mod stl;
+mod gltf;
+
+pub use self::obj::ObjLoader;
+pub use self::ply::PlyLoader;
+pub use self::stl::StlLoader;
+pub use self::gltf::GltfLoader;
+pub(crate) use self::gltf::{primitive_instances, read_buffers};
+
+/// Reads one mesh file format into `Mesh`
+pub trait MeshLoader: Send + Sync {
+    /// Lowercase extensions without dot
+    fn extensions(&self) -> &[&str];
+
+    /// Checks leading bytes of file, formats without magic keep the default
+    fn matches_magic(&self, _bytes: &[u8]) -> bool {
+        false
+    }
+
+    /// `path` is used for resolving files referenced by the mesh
+    fn load(&self, path: &Path, bytes: &[u8]) -> Result<Mesh>;
+}
+
+/// Set of loaders, format is picked by magic bytes first and by extension second
+pub struct Loaders {
+    loaders: Vec<Box<dyn MeshLoader>>
+}
+
+impl Loaders {
+    pub fn empty() -> Self {
+        Loaders { loaders: Vec::new() }
+    }
+
+    /// Adds loader, it takes precedence over loaders registered before
+    pub fn register<L: MeshLoader + 'static>(&mut self, loader: L) {
+        self.loaders.insert(0, Box::new(loader));
+    }
+
+    /// Whether some loader handles extension of `path`
+    pub fn supports(&self, path: &Path) -> bool {
+        self.by_extension(path).is_some()
+    }
+
+    pub fn load(&self, path: &Path) -> Result<Mesh> {
+        let bytes = fs::read(path)?;
+        let loader = self.loaders.iter().find(|loader| loader.matches_magic(&bytes)).map(|loader| loader.as_ref())
+            .or_else(|| self.by_extension(path))
+            .ok_or_else(|| Error::UnsupportedFormat(path.to_path_buf()))?;
+        let mesh = loader.load(path, &bytes)?;
+        if mesh.indices.is_empty() {
+            return Err(Error::EmptyMesh);
+        }
+        Ok(mesh)
+    }
+
+    fn by_extension(&self, path: &Path) -> Option<&dyn MeshLoader> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.loaders.iter().find(|loader| loader.extensions().contains(&ext.as_str())).map(|loader| loader.as_ref())
+    }
+}
+
+impl Default for Loaders {
+    /// Loaders of all built-in formats
+    fn default() -> Self {
+        let mut loaders = Loaders::empty();
+        loaders.register(ObjLoader::default());
+        loaders.register(PlyLoader);
+        loaders.register(StlLoader);
+        loaders.register(GltfLoader);
+        loaders
+    }
+}
+
+/// Loads mesh with built-in loaders
+pub fn load_mesh(path: &Path) -> Result<Mesh> {
+    Loaders::default().load(path)
+}
@@ -0,0 +1,220 @@
+use std::path::Path;
+use std::fs;
+
+use ::gltf::{Gltf, Node, Semantic, buffer::Source, mesh::Mode};
+use cgmath::{Matrix3, Matrix4, Point3, Vector3, SquareMatrix, prelude::*};
+
+use crate::error::{Error, Result};
+use crate::geo::{compute_normals, Mesh, MeshObject, Vertex};
+use super::MeshLoader;
+
+/// glTF 2.0 as .gltf with external or embedded buffers or as binary .glb.
+///
+/// Triangle primitives of all meshes in the default scene are merged with node transforms applied, each
+/// becomes an object named after its mesh.
+pub struct GltfLoader;
+
+impl MeshLoader for GltfLoader {
+    fn extensions(&self) -> &[&str] {
+        &["gltf", "glb"]
+    }
+
+    fn matches_magic(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(b"glTF")
+    }
+
+    fn load(&self, path: &Path, bytes: &[u8]) -> Result<Mesh> {
+        let gltf = Gltf::from_slice(bytes).map_err(Error::parse)?;
+        let buffers = read_buffers(&gltf, path)?;
+
+        let mut mesh = Mesh::default();
+        for instance in primitive_instances(&gltf, &mut mesh.warnings)? {
+            let gltf_mesh = gltf.meshes().nth(instance.mesh).unwrap();
+            let primitive = gltf_mesh.primitives().nth(instance.primitive).unwrap();
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let positions: Vec<[f32; 3]> = reader.read_positions().map(Iterator::collect).unwrap_or_default();
+            let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+            let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect());
+
+            let transform = instance.transform;
+            let normal_matrix = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate())
+                .invert().unwrap_or_else(Matrix3::identity).transpose();
+
+            let offset = mesh.verts.len() as u32;
+            for (i, &pos) in positions.iter().enumerate() {
+                let normal = normals.as_ref().and_then(|normals| normals.get(i)).map(|&normal| {
+                    let normal = normal_matrix * Vector3::from(normal);
+                    if normal.magnitude2() > 0.0 { normal.normalize().into() } else { normal.into() }
+                }).unwrap_or_default();
+                mesh.verts.push(Vertex {
+                    // glTF has UV origin in top left corner
+                    uv: uvs.as_ref().and_then(|uvs| uvs.get(i)).map(|&[u, v]| [u, 1.0 - v]).unwrap_or_default(),
+                    ..Vertex::new(transform.transform_point(Point3::from(pos)).into(), normal)
+                });
+            }
+
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect()
+            };
+            if indices.iter().any(|&i| i as usize >= positions.len()) {
+                return Err(Error::parse("index out of range"));
+            }
+            let mut indices = triangle_list(primitive.mode(), indices);
+            // mirroring transforms flip winding
+            if transform.determinant() < 0.0 {
+                for tri in indices.chunks_exact_mut(3) {
+                    tri.swap(1, 2);
+                }
+            }
+            if normals.is_none() {
+                compute_normals(&mut mesh.verts[offset as usize..], &indices);
+            }
+
+            let name = gltf_mesh.name().map(str::to_string).unwrap_or_else(|| format!("mesh{}", instance.mesh));
+            mesh.objects.push(MeshObject { name, indices: mesh.indices.len()..mesh.indices.len() + indices.len() });
+            mesh.indices.extend(indices.iter().map(|&i| i + offset));
+            mesh.has_uvs |= uvs.is_some();
+        }
+        Ok(mesh)
+    }
+}
+
+/// Converts indices of triangle strip or fan into triangle list, degenerate strip triangles are dropped
+fn triangle_list(mode: Mode, mut indices: Vec<u32>) -> Vec<u32> {
+    match mode {
+        Mode::TriangleStrip => (0..indices.len().saturating_sub(2)).flat_map(|i| {
+            // every other triangle has flipped order to keep winding
+            let tri = if i % 2 == 0 { [indices[i], indices[i + 1], indices[i + 2]] } else { [indices[i + 1], indices[i], indices[i + 2]] };
+            if tri[0] == tri[1] || tri[1] == tri[2] || tri[0] == tri[2] { Vec::new() } else { tri.to_vec() }
+        }).collect(),
+        Mode::TriangleFan => (1..indices.len().saturating_sub(1)).flat_map(|i| vec![indices[0], indices[i], indices[i + 1]]).collect(),
+        _ => {
+            indices.truncate(indices.len() / 3 * 3);
+            indices
+        }
+    }
+}
+
+/// Triangle primitive placed in the scene by a node
+pub(crate) struct PrimitiveInstance {
+    pub transform: Matrix4<f32>,
+    pub mesh: usize,
+    pub primitive: usize,
+    pub vertex_count: usize
+}
+
+/// Triangle, strip and fan primitives of the default scene in the order their vertices are merged into `Mesh`,
+/// skipped primitives of other modes are reported in `warnings`
+pub(crate) fn primitive_instances(gltf: &Gltf, warnings: &mut Vec<String>) -> Result<Vec<PrimitiveInstance>> {
+    let scene = gltf.default_scene().or_else(|| gltf.scenes().next())
+        .ok_or_else(|| Error::parse("no scene in file"))?;
+    let mut instances = Vec::new();
+    for node in scene.nodes() {
+        add_node(&node, Matrix4::identity(), &mut instances, warnings);
+    }
+    Ok(instances)
+}
+
+fn add_node(node: &Node, parent: Matrix4<f32>, instances: &mut Vec<PrimitiveInstance>, warnings: &mut Vec<String>) {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if ![Mode::Triangles, Mode::TriangleStrip, Mode::TriangleFan].contains(&primitive.mode()) {
+                warnings.push(format!("skipped {:?} primitive of mesh {:?}", primitive.mode(), mesh.name().unwrap_or_default()));
+                continue;
+            }
+            if let Some(positions) = primitive.get(&Semantic::Positions) {
+                instances.push(PrimitiveInstance {
+                    transform,
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                    vertex_count: positions.count()
+                });
+            }
+        }
+    }
+
+    for child in node.children() {
+        add_node(&child, transform, instances, warnings);
+    }
+}
+
+/// Data of every buffer, `path` is the glTF file relative URIs are resolved against
+pub(crate) fn read_buffers(gltf: &Gltf, path: &Path) -> Result<Vec<Vec<u8>>> {
+    gltf.buffers().map(|buffer| match buffer.source() {
+        Source::Bin => gltf.blob.clone().ok_or_else(|| Error::parse("missing binary chunk")),
+        Source::Uri(uri) => read_uri(path, uri)
+    }).collect()
+}
+
+/// Buffers are either base64 data URIs or files relative to the glTF file
+fn read_uri(path: &Path, uri: &str) -> Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        let (_, data) = uri.split_once(',').ok_or_else(|| Error::parse("invalid data URI"))?;
+        return base64::decode(data).map_err(Error::parse);
+    }
+    Ok(fs::read(path.with_file_name(uri))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(bytes: &[u8]) -> Result<Mesh> {
+        GltfLoader.load(Path::new("test.glb"), bytes)
+    }
+
+    /// Binary glTF with one triangle made of `indices`
+    fn glb(indices: [u32; 3]) -> Vec<u8> {
+        let mut bin = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bin.extend(&value.to_le_bytes());
+        }
+        for index in indices {
+            bin.extend(&index.to_le_bytes());
+        }
+        let json = format!(r#"{{"asset":{{"version":"2.0"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],
+            "meshes":[{{"name":"tri","primitives":[{{"attributes":{{"POSITION":0}},"indices":1}}]}}],
+            "accessors":[{{"bufferView":0,"componentType":5126,"count":3,"type":"VEC3","min":[0,0,0],"max":[1,1,0]}},
+                {{"bufferView":1,"componentType":5125,"count":3,"type":"SCALAR"}}],
+            "bufferViews":[{{"buffer":0,"byteOffset":0,"byteLength":36}},{{"buffer":0,"byteOffset":36,"byteLength":12}}],
+            "buffers":[{{"byteLength":{}}}]}}"#, bin.len());
+        let mut json = json.into_bytes();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend(&2u32.to_le_bytes());
+        bytes.extend(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        bytes.extend(&(json.len() as u32).to_le_bytes());
+        bytes.extend(b"JSON");
+        bytes.extend(&json);
+        bytes.extend(&(bin.len() as u32).to_le_bytes());
+        bytes.extend(b"BIN\0");
+        bytes.extend(&bin);
+        bytes
+    }
+
+    #[test]
+    fn binary_gltf() {
+        let mesh = load(&glb([0, 1, 2])).unwrap();
+        assert_eq!(mesh.verts.iter().map(|vert| vert.pos).collect::<Vec<_>>(), [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        // normals are computed when missing
+        assert!(mesh.verts.iter().all(|vert| vert.normal == [0.0, 0.0, 1.0]));
+        assert_eq!(mesh.objects[0].name, "tri");
+    }
+
+    #[test]
+    fn truncated_input_fails() {
+        let bytes = glb([0, 1, 2]);
+        assert!(matches!(load(&bytes[..bytes.len() - 6]), Err(Error::Parse { .. })));
+        assert!(matches!(load(&bytes[..40]), Err(Error::Parse { .. })));
+    }
+
+    #[test]
+    fn out_of_range_index_fails() {
+        assert!(matches!(load(&glb([0, 1, 3])), Err(Error::Parse { .. })));
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use wavefront_obj::obj;
+
+use crate::error::{Error, Result};
+use crate::geo::{generate_vector_buffer, triangulate, Mesh};
+use super::MeshLoader;
+
+/// Wavefront .obj, all objects and groups of the file are merged keeping their names.
+///
+/// Objects without normals get smooth ones, split at edges sharper than `crease_angle` degrees.
+#[derive(Default)]
+pub struct ObjLoader {
+    pub crease_angle: Option<f32>
+}
+
+impl MeshLoader for ObjLoader {
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+
+    fn load(&self, _path: &Path, bytes: &[u8]) -> Result<Mesh> {
+        let content = String::from_utf8_lossy(bytes);
+        let mut warnings = Vec::new();
+        let (content, source_lines) = triangulate_faces(&content, &mut warnings);
+        let obj_set = obj::parse(content).map_err(|e| {
+            let line = source_lines.get(e.line_number.wrapping_sub(1)).copied().unwrap_or(e.line_number);
+            Error::Parse { line: Some(line), message: e.message }
+        })?;
+        let mut mesh = Mesh::default();
+        for object in &obj_set.objects {
+            mesh.append(generate_vector_buffer(object, self.crease_angle));
+        }
+        mesh.warnings = warnings;
+        Ok(mesh)
+    }
+}
+
+/// Splits polygon faces into triangles by `triangulate`, as the parser would only fan them. Point and
+/// line elements, which the parser rejects, are left out and reported in `warnings`. Returns the new
+/// content and source line number of each of its lines.
+fn triangulate_faces(content: &str, warnings: &mut Vec<String>) -> (String, Vec<usize>) {
+    let mut output = String::with_capacity(content.len());
+    let mut source_lines = Vec::new();
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let (mut points, mut lines) = (0, 0);
+
+    for (number, line) in content.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let mut pos = [0.0; 3];
+                for (value, word) in pos.iter_mut().zip(words) {
+                    *value = word.parse().unwrap_or_default();
+                }
+                positions.push(pos);
+            },
+            Some("p") => {
+                // every vertex of the element is a point of its own
+                points += words.count();
+                continue;
+            },
+            Some("l") => {
+                lines += 1;
+                continue;
+            },
+            Some("f") => {
+                let corners: Vec<&str> = words.collect();
+                match corners.len() {
+                    0..=1 => points += 1,
+                    2 => lines += 1,
+                    3 => push_line(&mut output, &mut source_lines, line, number),
+                    _ => {
+                        // unresolvable indices are left for the parser to report
+                        let polygon: Option<Vec<[f32; 3]>> = corners.iter()
+                            .map(|corner| position_index(corner, positions.len()).map(|i| positions[i]))
+                            .collect();
+                        match polygon {
+                            Some(polygon) => for [a, b, c] in triangulate(&polygon) {
+                                let face = format!("f {} {} {}", corners[a], corners[b], corners[c]);
+                                push_line(&mut output, &mut source_lines, &face, number);
+                            },
+                            None => push_line(&mut output, &mut source_lines, line, number)
+                        }
+                    }
+                }
+                continue;
+            },
+            _ => {}
+        }
+        push_line(&mut output, &mut source_lines, line, number);
+    }
+
+    if points + lines > 0 {
+        warnings.push(format!("ignored {} points and {} lines, only faces are baked", points, lines));
+    }
+    (output, source_lines)
+}
+
+fn push_line(output: &mut String, source_lines: &mut Vec<usize>, line: &str, number: usize) {
+    output.push_str(line);
+    output.push('\n');
+    source_lines.push(number + 1);
+}
+
+/// Resolves 1-based or negative relative position index of `v/vt/vn` corner
+fn position_index(corner: &str, count: usize) -> Option<usize> {
+    let index: isize = corner.split('/').next()?.parse().ok()?;
+    let index = if index < 0 { count as isize + index } else { index - 1 };
+    if index >= 0 && (index as usize) < count { Some(index as usize) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_and_lines_are_skipped() {
+        let content = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\np 4\np 1 2\nl 1 3\nf 1 2\n";
+        let mut warnings = Vec::new();
+        let (output, source_lines) = triangulate_faces(content, &mut warnings);
+        assert!(output.lines().all(|line| !line.starts_with('p') && !line.starts_with('l')));
+        assert_eq!(source_lines, [1, 2, 3, 4, 5, 5]);
+
+        let mesh = ObjLoader::default().load(Path::new("points.obj"), content.as_bytes()).unwrap();
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(mesh.warnings, ["ignored 3 points and 2 lines, only faces are baked"]);
+    }
+}
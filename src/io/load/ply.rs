@@ -0,0 +1,370 @@
+use std::path::Path;
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::geo::{compute_normals, triangulate, Mesh, Vertex};
+use super::MeshLoader;
+
+/// Stanford .ply in ASCII or binary encoding, polygons are triangulated and vertex normals are computed when missing
+pub struct PlyLoader;
+
+impl MeshLoader for PlyLoader {
+    fn extensions(&self) -> &[&str] {
+        &["ply"]
+    }
+
+    fn matches_magic(&self, bytes: &[u8]) -> bool {
+        bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n")
+    }
+
+    fn load(&self, _path: &Path, bytes: &[u8]) -> Result<Mesh> {
+        let (header, body) = parse_header(bytes)?;
+        let mut values = match header.format {
+            Format::Ascii => {
+                let text = std::str::from_utf8(body).map_err(Error::parse)?;
+                Values::Ascii(text.split_ascii_whitespace())
+            },
+            Format::BinaryLittleEndian => Values::Binary { data: body, pos: 0, big_endian: false },
+            Format::BinaryBigEndian => Values::Binary { data: body, pos: 0, big_endian: true }
+        };
+
+        let mut verts = Vec::new();
+        let mut indices = Vec::new();
+        let mut has_normals = false;
+        let mut has_uvs = false;
+        let mut warnings = Vec::new();
+
+        for element in &header.elements {
+            match element.name.as_str() {
+                "vertex" => {
+                    let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()));
+                    let pos = [find(&["x"]), find(&["y"]), find(&["z"])];
+                    let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+                    let uv = [find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])];
+                    if pos.iter().any(Option::is_none) {
+                        return Err(Error::parse("vertex element is missing position"));
+                    }
+                    has_normals = normal.iter().all(Option::is_some);
+                    has_uvs = uv.iter().all(Option::is_some);
+
+                    let mut row = vec![0.0; element.properties.len()];
+                    for _ in 0..element.count {
+                        for (value, property) in row.iter_mut().zip(&element.properties) {
+                            *value = match property.kind {
+                                PropertyKind::Scalar(ty) => values.read(ty)?,
+                                PropertyKind::List(count_ty, item_ty) => {
+                                    values.skip_list(count_ty, item_ty)?;
+                                    0.0
+                                }
+                            };
+                        }
+                        let get = |i: Option<usize>| i.map(|i| row[i] as f32).unwrap_or_default();
+                        verts.push(Vertex {
+                            uv: [get(uv[0]), get(uv[1])],
+                            ..Vertex::new([get(pos[0]), get(pos[1]), get(pos[2])], [get(normal[0]), get(normal[1]), get(normal[2])])
+                        });
+                    }
+                },
+                "face" => {
+                    let list = element.properties.iter().position(|p| p.name == "vertex_indices" || p.name == "vertex_index")
+                        .ok_or_else(|| Error::parse("face element is missing vertex indices"))?;
+                    let mut polygon = Vec::new();
+                    for _ in 0..element.count {
+                        for (i, property) in element.properties.iter().enumerate() {
+                            match property.kind {
+                                PropertyKind::List(count_ty, item_ty) if i == list => {
+                                    polygon.clear();
+                                    let count = values.read(count_ty)? as usize;
+                                    for _ in 0..count {
+                                        // casting would turn negative indices into zero
+                                        let index = values.read(item_ty)?;
+                                        if index < 0.0 {
+                                            return Err(Error::parse("face index out of range"));
+                                        }
+                                        polygon.push(index as u32);
+                                    }
+                                },
+                                PropertyKind::List(count_ty, item_ty) => values.skip_list(count_ty, item_ty)?,
+                                PropertyKind::Scalar(ty) => {
+                                    values.read(ty)?;
+                                }
+                            }
+                        }
+                        let positions: Option<Vec<[f32; 3]>> = polygon.iter().map(|&i| verts.get(i as usize).map(|vert| vert.pos)).collect();
+                        match positions {
+                            Some(positions) => for tri in triangulate(&positions) {
+                                indices.extend(tri.iter().map(|&i| polygon[i]));
+                            },
+                            // out of range indices are reported below
+                            None => indices.extend_from_slice(&polygon)
+                        }
+                    }
+                },
+                "edge" => {
+                    if element.count > 0 {
+                        warnings.push(format!("ignored {} edges, only faces are baked", element.count));
+                    }
+                    values.skip_element(element)?
+                },
+                _ => values.skip_element(element)?
+            }
+        }
+
+        if indices.iter().any(|&i| i as usize >= verts.len()) {
+            return Err(Error::parse("face index out of range"));
+        }
+        if !has_normals {
+            compute_normals(&mut verts, &indices);
+        }
+        Ok(Mesh { verts, indices, has_uvs, objects: Vec::new(), warnings })
+    }
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return Err(Error::parse(format!("unknown property type `{}`", name)))
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8
+        }
+    }
+}
+
+enum PropertyKind {
+    Scalar(ScalarType),
+    /// count type and item type
+    List(ScalarType, ScalarType)
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>
+}
+
+/// Returns parsed header and the body following it
+fn parse_header(bytes: &[u8]) -> Result<(Header, &[u8])> {
+    const END: &[u8] = b"end_header";
+    let end = bytes.windows(END.len()).position(|window| window == END)
+        .ok_or_else(|| Error::parse("missing end_header"))?;
+    let body_start = bytes[end..].iter().position(|&b| b == b'\n').map(|i| end + i + 1).unwrap_or(bytes.len());
+    let text = String::from_utf8_lossy(&bytes[..end]);
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in text.lines().skip(1) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", ..] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", ..] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", ..] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| Error::parse(format!("invalid element count `{}`", count)))?,
+                properties: Vec::new()
+            }),
+            ["property", "list", count_ty, item_ty, name] => {
+                let kind = PropertyKind::List(ScalarType::parse(count_ty)?, ScalarType::parse(item_ty)?);
+                elements.last_mut().ok_or_else(|| Error::parse("property outside of element"))?
+                    .properties.push(Property { name: name.to_string(), kind });
+            },
+            ["property", ty, name] => {
+                let kind = PropertyKind::Scalar(ScalarType::parse(ty)?);
+                elements.last_mut().ok_or_else(|| Error::parse("property outside of element"))?
+                    .properties.push(Property { name: name.to_string(), kind });
+            },
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| Error::parse("missing format"))?;
+    Ok((Header { format, elements }, &bytes[body_start..]))
+}
+
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool
+    }
+}
+
+impl<'a> Values<'a> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64> {
+        match self {
+            Values::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(|| Error::parse("unexpected end of file"))?;
+                token.parse().map_err(|_| Error::parse(format!("invalid value `{}`", token)))
+            },
+            Values::Binary { data, pos, big_endian } => {
+                let bytes = data.get(*pos..*pos + ty.size()).ok_or_else(|| Error::parse("unexpected end of file"))?;
+                *pos += ty.size();
+                Ok(decode(bytes, ty, *big_endian))
+            }
+        }
+    }
+
+    fn skip_list(&mut self, count_ty: ScalarType, item_ty: ScalarType) -> Result<()> {
+        let count = self.read(count_ty)? as usize;
+        for _ in 0..count {
+            self.read(item_ty)?;
+        }
+        Ok(())
+    }
+
+    fn skip_element(&mut self, element: &Element) -> Result<()> {
+        for _ in 0..element.count {
+            for property in &element.properties {
+                match property.kind {
+                    PropertyKind::Scalar(ty) => {
+                        self.read(ty)?;
+                    },
+                    PropertyKind::List(count_ty, item_ty) => self.skip_list(count_ty, item_ty)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decode(bytes: &[u8], ty: ScalarType, big_endian: bool) -> f64 {
+    macro_rules! num {
+        ($t:ty) => {{
+            let bytes = bytes.try_into().unwrap();
+            (if big_endian { <$t>::from_be_bytes(bytes) } else { <$t>::from_le_bytes(bytes) }) as f64
+        }};
+    }
+    match ty {
+        ScalarType::I8 => bytes[0] as i8 as f64,
+        ScalarType::U8 => bytes[0] as f64,
+        ScalarType::I16 => num!(i16),
+        ScalarType::U16 => num!(u16),
+        ScalarType::I32 => num!(i32),
+        ScalarType::U32 => num!(u32),
+        ScalarType::F32 => num!(f32),
+        ScalarType::F64 => num!(f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+
+    fn load(bytes: &[u8]) -> Result<Mesh> {
+        PlyLoader.load(Path::new("test.ply"), bytes)
+    }
+
+    /// Unit square as one quad, face has a list property before indices and a scalar one after them
+    fn binary(big_endian: bool, quad: [i32; 4]) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut bytes = format!("ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int flags\nproperty list uchar int vertex_indices\nproperty uchar material\nend_header\n", format).into_bytes();
+        let mut push = |value: &[u8]| if big_endian { bytes.extend(value.iter().rev()) } else { bytes.extend(value) };
+        for value in POSITIONS.iter().flatten() {
+            push(&value.to_le_bytes());
+        }
+        push(&[2]);
+        push(&7i32.to_le_bytes());
+        push(&8i32.to_le_bytes());
+        push(&[4]);
+        for index in &quad {
+            push(&index.to_le_bytes());
+        }
+        push(&[1]);
+        bytes
+    }
+
+    fn assert_square(mesh: &Mesh) {
+        assert_eq!(mesh.verts.iter().map(|vert| vert.pos).collect::<Vec<_>>(), POSITIONS);
+        assert_eq!(mesh.indices.len(), 6);
+        assert!(mesh.verts.iter().all(|vert| vert.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn ascii() {
+        let ply = "ply\nformat ascii 1.0\ncomment square\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int flags\nproperty list uchar int vertex_indices\nproperty uchar material\nend_header\n\
+            0 0 0\n1 0 0\n1 1 0\n0 1 0\n2 7 8 4 0 1 2 3 1\n";
+        assert_square(&load(ply.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn edges_are_reported() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nelement edge 2\nproperty int vertex1\nproperty int vertex2\nend_header\n\
+            0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n0 2\n1 3\n";
+        let mesh = load(ply.as_bytes()).unwrap();
+        assert_square(&mesh);
+        assert_eq!(mesh.warnings, ["ignored 2 edges, only faces are baked"]);
+    }
+
+    #[test]
+    fn binary_endianness() {
+        let little = load(&binary(false, [0, 1, 2, 3])).unwrap();
+        let big = load(&binary(true, [0, 1, 2, 3])).unwrap();
+        assert_square(&little);
+        assert_square(&big);
+        assert_eq!(little.indices, big.indices);
+    }
+
+    #[test]
+    fn truncated_input_fails() {
+        let bytes = binary(false, [0, 1, 2, 3]);
+        assert!(matches!(load(&bytes[..bytes.len() - 3]), Err(Error::Parse { .. })));
+        let ply = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n1 0";
+        assert!(matches!(load(ply.as_bytes()), Err(Error::Parse { .. })));
+        assert!(matches!(load(b"ply\nformat ascii 1.0\nelement vertex 4\n"), Err(Error::Parse { .. })));
+    }
+
+    #[test]
+    fn out_of_range_index_fails() {
+        assert!(matches!(load(&binary(false, [0, 1, 2, 4])), Err(Error::Parse { .. })));
+        assert!(matches!(load(&binary(true, [0, 1, -1, 3])), Err(Error::Parse { .. })));
+    }
+}
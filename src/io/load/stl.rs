@@ -0,0 +1,165 @@
+use std::path::Path;
+use std::convert::TryInto;
+
+use cgmath::{Vector3, prelude::*};
+
+use crate::error::{Error, Result};
+use crate::geo::{Mesh, Vertex};
+use super::MeshLoader;
+
+/// Stereolithography .stl in ASCII or binary encoding, triangles keep their facet normals
+pub struct StlLoader;
+
+const HEADER_SIZE: usize = 84;
+const TRIANGLE_SIZE: usize = 50;
+
+impl MeshLoader for StlLoader {
+    fn extensions(&self) -> &[&str] {
+        &["stl"]
+    }
+
+    fn load(&self, _path: &Path, bytes: &[u8]) -> Result<Mesh> {
+        let triangles = if is_binary(bytes) { parse_binary(bytes)? } else { parse_ascii(bytes)? };
+
+        let mut verts = Vec::with_capacity(triangles.len() * 3);
+        for (normal, corners) in triangles {
+            let normal = facet_normal(normal, &corners);
+            verts.extend(corners.iter().map(|&pos| Vertex::new(pos, normal)));
+        }
+        let indices = (0..verts.len() as u32).collect();
+        Ok(Mesh { verts, indices, has_uvs: false, objects: Vec::new(), warnings: Vec::new() })
+    }
+}
+
+type Facet = ([f32; 3], [[f32; 3]; 3]);
+
+/// Binary files may start with `solid` too, their size is decisive
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() < HEADER_SIZE {
+        return false;
+    }
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    bytes.len() == HEADER_SIZE + count * TRIANGLE_SIZE || !bytes.starts_with(b"solid")
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Facet>> {
+    let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+    let data = bytes.get(HEADER_SIZE..HEADER_SIZE + count * TRIANGLE_SIZE).ok_or_else(|| Error::parse("unexpected end of file"))?;
+    let float = |chunk: &[u8], i: usize| f32::from_le_bytes(chunk[i * 4..i * 4 + 4].try_into().unwrap());
+    Ok(data.chunks_exact(TRIANGLE_SIZE).map(|chunk| {
+        let vec = |i: usize| [float(chunk, i), float(chunk, i + 1), float(chunk, i + 2)];
+        (vec(0), [vec(3), vec(6), vec(9)])
+    }).collect())
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<Facet>> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut tokens = text.split_whitespace();
+    let mut triangles = Vec::new();
+    let mut normal = [0.0; 3];
+    let mut corners = Vec::with_capacity(3);
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "normal" => normal = read_vec(&mut tokens)?,
+            "vertex" => corners.push(read_vec(&mut tokens)?),
+            "endfacet" => {
+                if corners.len() != 3 {
+                    return Err(Error::parse("facet doesn't have 3 vertices"));
+                }
+                triangles.push((normal, [corners[0], corners[1], corners[2]]));
+                corners.clear();
+            },
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+fn read_vec(tokens: &mut std::str::SplitWhitespace) -> Result<[f32; 3]> {
+    let mut vec = [0.0; 3];
+    for value in &mut vec {
+        let token = tokens.next().ok_or_else(|| Error::parse("unexpected end of file"))?;
+        *value = token.parse().map_err(|_| Error::parse(format!("invalid number `{}`", token)))?;
+    }
+    Ok(vec)
+}
+
+/// Stored normal is often zero, it's computed from winding then
+fn facet_normal(normal: [f32; 3], corners: &[[f32; 3]; 3]) -> [f32; 3] {
+    let normal = Vector3::from(normal);
+    if normal.magnitude2() > 0.0 {
+        return normal.normalize().into();
+    }
+    let a = Vector3::from(corners[0]);
+    let cross = (Vector3::from(corners[1]) - a).cross(Vector3::from(corners[2]) - a);
+    if cross.magnitude2() > 0.0 {
+        cross.normalize().into()
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FACETS: [Facet; 2] = [
+        ([0.0, 0.0, 1.0], [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]),
+        // missing normal is computed from winding
+        ([0.0, 0.0, 0.0], [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]])
+    ];
+
+    fn load(bytes: &[u8]) -> Result<Mesh> {
+        StlLoader.load(Path::new("test.stl"), bytes)
+    }
+
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, 0);
+        bytes.extend(&(FACETS.len() as u32).to_le_bytes());
+        for (normal, corners) in &FACETS {
+            for value in normal.iter().chain(corners.iter().flatten()) {
+                bytes.extend(&value.to_le_bytes());
+            }
+            bytes.extend(&[0, 0]);
+        }
+        bytes
+    }
+
+    fn ascii() -> String {
+        let mut text = "solid square\n".to_string();
+        for (normal, corners) in &FACETS {
+            text += &format!("facet normal {} {} {}\nouter loop\n", normal[0], normal[1], normal[2]);
+            for corner in corners {
+                text += &format!("vertex {} {} {}\n", corner[0], corner[1], corner[2]);
+            }
+            text += "endloop\nendfacet\n";
+        }
+        text + "endsolid square\n"
+    }
+
+    fn assert_square(mesh: &Mesh) {
+        let positions: Vec<[f32; 3]> = FACETS.iter().flat_map(|(_, corners)| corners.iter().copied()).collect();
+        assert_eq!(mesh.verts.iter().map(|vert| vert.pos).collect::<Vec<_>>(), positions);
+        assert!(mesh.verts.iter().all(|vert| vert.normal == [0.0, 0.0, 1.0]));
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        assert_square(&load(ascii().as_bytes()).unwrap());
+        assert_square(&load(&binary(b"")).unwrap());
+        // size tells binary files apart from ASCII ones even when their header starts with `solid`
+        assert_square(&load(&binary(b"solid binary")).unwrap());
+    }
+
+    #[test]
+    fn truncated_input_fails() {
+        let bytes = binary(b"");
+        assert!(matches!(load(&bytes[..bytes.len() - 10]), Err(Error::Parse { .. })));
+        let text = ascii();
+        assert!(matches!(load(&text.as_bytes()[..text.find("endloop").unwrap() - 3]), Err(Error::Parse { .. })));
+        assert!(matches!(load(b"solid x\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nendloop\nendfacet\n"), Err(Error::Parse { .. })));
+    }
+}
@@ -1,3 +1,7 @@
+//! Ambient occlusion baking for triangle meshes.
+//!
+//! Use [`Baker`] to bake AO from Rust code, or [`Window`] for interactive preview.
+
 mod world_data;
 mod consts;
 mod io;
@@ -5,5 +9,16 @@ mod render;
 mod geo;
 mod compute;
 mod window;
+mod cli;
+mod baker;
+mod error;
 
-pub use window::Window;
\ No newline at end of file
+pub use error::{Error, Result};
+pub use window::Window;
+pub use cli::run as run_cli;
+pub use baker::{Baker, BakeHandle};
+pub use compute::texture::{NormalMap, Texture};
+pub use geo::{Mesh, MeshObject, Vertex};
+pub use io::load::{load_mesh, Loaders, MeshLoader};
+pub use io::image::{save_texture, save_normal_map, pack_texture, ImageFormat, Channel, Packing};
+pub use compute::{ComputeData, CancelToken, Progress, AccelStructure, RayDistance, Falloff, Distribution, Sequence, Adaptive, BakeTarget, BakeMode, GroundPlane};
\ No newline at end of file
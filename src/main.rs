@@ -1,9 +1,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 use glium::glutin::event_loop::EventLoop;
 
-use ao_baker::Window;
+use ao_baker::{Window, run_cli};
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some("bake") = args.first().map(String::as_str) {
+        std::process::exit(run_cli(&args[1..]));
+    }
+
     let event_loop = EventLoop::new();
     let mut window = Window::new(&event_loop);
     event_loop.run(move |event, _wt, control_flow| window.event_handler(event, control_flow));
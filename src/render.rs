@@ -15,7 +15,7 @@ use crate::geo::{Vertex, VertexUV};
 
 use cgmath::{perspective, Deg, Matrix4, Matrix3};
 
-use rusttype::FontCollection;
+use rusttype::{Font, FontCollection};
 
 mod tooltips;
 
@@ -34,6 +34,10 @@ pub struct Renderer {
     quad_vbuffer: VertexBuffer<VertexUV>,
     tooltip_textures: Vec<Texture2d>,
     tooltip_transform: Matrix3<f32>,
+    font: Font<'static>,
+    /// error shown on top of the window, it can be set from any thread
+    pub message: Arc<Mutex<Option<String>>>,
+    message_texture: Option<(String, Texture2d)>,
     grid_vbuffer: VertexBuffer<Vertex>,
     grid_program: Program
 }
@@ -41,7 +45,7 @@ pub struct Renderer {
 impl Renderer {
     pub fn new(event_loop: &EventLoop<()>, wb: WindowBuilder) -> Self {
         let cb = glium::glutin::ContextBuilder::new().with_depth_buffer(16).with_srgb(false);
-        let display = glium::Display::new(wb, cb, &event_loop).unwrap();
+        let display = glium::Display::new(wb, cb, event_loop).unwrap();
 
         let (program, program_tooltip, grid_program) = Self::make_programs(&display);
 
@@ -115,6 +119,9 @@ impl Renderer {
             quad_vbuffer,
             tooltip_textures,
             tooltip_transform,
+            font,
+            message: Arc::new(Mutex::new(None)),
+            message_texture: None,
             grid_vbuffer,
             grid_program,
         }
@@ -130,41 +137,41 @@ impl Renderer {
             if i == 0 {
                 continue;
             }
-            vec.push(Vertex{normal, color, pos: [i as f32 + line_width, 0.0, 10.0]});
-            vec.push(Vertex{normal, color, pos: [i as f32 - line_width, 0.0, -10.0]});
-            vec.push(Vertex{normal, color, pos: [i as f32 - line_width, 0.0, 10.0]});
+            vec.push(Vertex { color, ..Vertex::new([i as f32 + line_width, 0.0, 10.0], normal) });
+            vec.push(Vertex { color, ..Vertex::new([i as f32 - line_width, 0.0, -10.0], normal) });
+            vec.push(Vertex { color, ..Vertex::new([i as f32 - line_width, 0.0, 10.0], normal) });
 
-            vec.push(Vertex{normal, color, pos: [i as f32 + line_width, 0.0, 10.0]});
-            vec.push(Vertex{normal, color, pos: [i as f32 + line_width, 0.0, -10.0]});
-            vec.push(Vertex{normal, color, pos: [i as f32 - line_width, 0.0, -10.0]});
+            vec.push(Vertex { color, ..Vertex::new([i as f32 + line_width, 0.0, 10.0], normal) });
+            vec.push(Vertex { color, ..Vertex::new([i as f32 + line_width, 0.0, -10.0], normal) });
+            vec.push(Vertex { color, ..Vertex::new([i as f32 - line_width, 0.0, -10.0], normal) });
 
-            vec.push(Vertex{normal, color, pos: [10.0, 0.0, i as f32 + line_width]});
-            vec.push(Vertex{normal, color, pos: [10.0, 0.0, i as f32 - line_width]});
-            vec.push(Vertex{normal, color, pos: [-10.0, 0.0, i as f32 - line_width]});
+            vec.push(Vertex { color, ..Vertex::new([10.0, 0.0, i as f32 + line_width], normal) });
+            vec.push(Vertex { color, ..Vertex::new([10.0, 0.0, i as f32 - line_width], normal) });
+            vec.push(Vertex { color, ..Vertex::new([-10.0, 0.0, i as f32 - line_width], normal) });
 
-            vec.push(Vertex{normal, color, pos: [10.0, 0.0, i as f32 + line_width]});
-            vec.push(Vertex{normal, color, pos: [-10.0, 0.0, i as f32 - line_width]});
-            vec.push(Vertex{normal, color, pos: [-10.0, 0.0, i as f32 + line_width]});
+            vec.push(Vertex { color, ..Vertex::new([10.0, 0.0, i as f32 + line_width], normal) });
+            vec.push(Vertex { color, ..Vertex::new([-10.0, 0.0, i as f32 - line_width], normal) });
+            vec.push(Vertex { color, ..Vertex::new([-10.0, 0.0, i as f32 + line_width], normal) });
         }
 
         let color_red = [0.8, 0.2, 0.2];
         let color_green = [0.2, 0.8, 0.2];
 
-        vec.push(Vertex{normal, color: color_green, pos: [0.01, 0.0, 10.0]});
-        vec.push(Vertex{normal, color: color_green, pos: [-0.01, 0.0, -10.0]});
-        vec.push(Vertex{normal, color: color_green, pos: [-0.01, 0.0, 10.0]});
+        vec.push(Vertex { color: color_green, ..Vertex::new([0.01, 0.0, 10.0], normal) });
+        vec.push(Vertex { color: color_green, ..Vertex::new([-0.01, 0.0, -10.0], normal) });
+        vec.push(Vertex { color: color_green, ..Vertex::new([-0.01, 0.0, 10.0], normal) });
 
-        vec.push(Vertex{normal, color: color_green, pos: [0.01, 0.0, 10.0]});
-        vec.push(Vertex{normal, color: color_green, pos: [0.01, 0.0, -10.0]});
-        vec.push(Vertex{normal, color: color_green, pos: [-0.01, 0.0, -10.0]});
+        vec.push(Vertex { color: color_green, ..Vertex::new([0.01, 0.0, 10.0], normal) });
+        vec.push(Vertex { color: color_green, ..Vertex::new([0.01, 0.0, -10.0], normal) });
+        vec.push(Vertex { color: color_green, ..Vertex::new([-0.01, 0.0, -10.0], normal) });
 
-        vec.push(Vertex{normal, color: color_red, pos: [10.0, 0.0, 0.01]});
-        vec.push(Vertex{normal, color: color_red, pos: [10.0, 0.0, -0.01]});
-        vec.push(Vertex{normal, color: color_red, pos: [-10.0, 0.0, -0.01]});
+        vec.push(Vertex { color: color_red, ..Vertex::new([10.0, 0.0, 0.01], normal) });
+        vec.push(Vertex { color: color_red, ..Vertex::new([10.0, 0.0, -0.01], normal) });
+        vec.push(Vertex { color: color_red, ..Vertex::new([-10.0, 0.0, -0.01], normal) });
 
-        vec.push(Vertex{normal, color: color_red, pos: [10.0, 0.0, 0.01]});
-        vec.push(Vertex{normal, color: color_red, pos: [-10.0, 0.0, -0.01]});
-        vec.push(Vertex{normal, color: color_red, pos: [-10.0, 0.0, 0.01]});
+        vec.push(Vertex { color: color_red, ..Vertex::new([10.0, 0.0, 0.01], normal) });
+        vec.push(Vertex { color: color_red, ..Vertex::new([-10.0, 0.0, -0.01], normal) });
+        vec.push(Vertex { color: color_red, ..Vertex::new([-10.0, 0.0, 0.01], normal) });
 
         glium::VertexBuffer::new(display, &vec).unwrap()
     }
@@ -222,7 +229,8 @@ impl Renderer {
 
             let tooltip_uniforms = uniform!(
                 font_texture: tooltip,
-                transform: Matrix3Wrapper(tooltip_transform)
+                transform: Matrix3Wrapper(tooltip_transform),
+                color: [1.0_f32; 3]
             );
 
             target.draw(
@@ -234,9 +242,47 @@ impl Renderer {
             ).unwrap();
         }
 
+        self.update_message_texture();
+        if let Some((_, message)) = &self.message_texture {
+            let mut message_transform = self.tooltip_transform.to_owned();
+            let (size_x, size_y) = self.display.get_framebuffer_dimensions();
+            let ratio = size_x as f32 / size_y as f32;
+
+            message_transform.y.z = 0.9;
+            message_transform.x.x = (0.1 / ratio) * message.width() as f32 / 64.0;
+
+            let message_uniforms = uniform!(
+                font_texture: message,
+                transform: Matrix3Wrapper(message_transform),
+                color: MESSAGE_COLOR
+            );
+
+            target.draw(
+                &self.quad_vbuffer,
+                glium::index::NoIndices(PrimitiveType::TrianglesList),
+                &self.program_tooltip,
+                &message_uniforms,
+                &self.draw_parameters
+            ).unwrap();
+        }
+
         target.finish().unwrap();
     }
 
+    /// Renders text of `message` when it changed since last frame
+    fn update_message_texture(&mut self) {
+        let message = self.message.lock().unwrap().to_owned();
+        let shown = self.message_texture.as_ref().map(|(text, _)| text);
+        if message.as_ref() == shown {
+            return;
+        }
+        self.message_texture = message.filter(|text| !text.is_empty()).map(|text| {
+            let (width, data) = tooltips::texture_data_from_str(&self.font, 64.0, &text);
+            let image = RawImage2d::from_raw_rgba_reversed(&data, (width as u32, 64));
+            (text, Texture2d::new(&self.display, image).unwrap())
+        });
+    }
+
     pub fn request_redraw(&self) {
         self.display.gl_window().window().request_redraw();
     }
@@ -324,7 +370,7 @@ impl VertexData {
 pub struct Matrix4Wrapper(pub cgmath::Matrix4<f32>);
 
 impl glium::uniforms::AsUniformValue for Matrix4Wrapper {
-    fn as_uniform_value(&self) -> UniformValue {
+    fn as_uniform_value(&self) -> UniformValue<'_> {
         UniformValue::Mat4(self.0.into())
     }
 }
@@ -332,7 +378,7 @@ impl glium::uniforms::AsUniformValue for Matrix4Wrapper {
 pub struct Matrix3Wrapper(pub cgmath::Matrix3<f32>);
 
 impl glium::uniforms::AsUniformValue for Matrix3Wrapper {
-    fn as_uniform_value(&self) -> UniformValue {
+    fn as_uniform_value(&self) -> UniformValue<'_> {
         UniformValue::Mat3(self.0.into())
     }
 }
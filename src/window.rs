@@ -4,12 +4,15 @@ use glium::glutin::event::{Event, WindowEvent, MouseScrollDelta, DeviceEvent, El
 
 use crate::consts::*;
 use crate::render::Renderer;
-use crate::geo::generate_vector_buffer;
-use crate::io::read_obj;
-use crate::compute::{compute_ao, ComputeData};
+use crate::io::load::{Loaders, MeshLoader};
+use crate::io::export::{export_baked, export_mesh, ExportFormat};
+use crate::io::image::{pack_texture, save_normal_map, save_texture, Channel, ImageFormat, Packing};
+use crate::compute::{check_mesh, compute_ao, BakeMode, BakeTarget, CancelToken, ComputeData, GroundPlane};
+use crate::compute::texture::{compute_texture, Texture};
+use crate::geo::{MeshObject, Vertex};
 
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 
 pub struct Window {
@@ -18,8 +21,18 @@ pub struct Window {
     is_middle_mouse_pressed: bool,
     is_focused: bool,
     bake_in_progress: Arc<AtomicBool>,
-    bake_stopper: Arc<AtomicBool>,
-    compute_data: ComputeData
+    bake_cancel: CancelToken,
+    compute_data: ComputeData,
+    export_format: ExportFormat,
+    opened_file: Option<PathBuf>,
+    mesh_indices: Vec<u32>,
+    mesh_has_uvs: bool,
+    mesh_objects: Vec<MeshObject>,
+    /// split meshes can't be exported into structure of input anymore
+    mesh_is_split: bool,
+    image_format: ImageFormat,
+    packing: Option<Packing>,
+    loaders: Loaders
 }
 
 impl Window {
@@ -36,10 +49,24 @@ impl Window {
             is_focused: false,
             bake_in_progress: Arc::new(AtomicBool::new(false)),
             compute_data: ComputeData::default(),
-            bake_stopper: Arc::new(AtomicBool::new(false))
+            bake_cancel: CancelToken::new(),
+            export_format: ExportFormat::default(),
+            opened_file: None,
+            mesh_indices: Vec::new(),
+            mesh_has_uvs: false,
+            mesh_objects: Vec::new(),
+            mesh_is_split: false,
+            image_format: ImageFormat::default(),
+            packing: None,
+            loaders: Loaders::default()
         }
     }
 
+    /// Makes dropped files of another format openable
+    pub fn register_loader<L: MeshLoader + 'static>(&mut self, loader: L) {
+        self.loaders.register(loader);
+    }
+
     pub fn event_handler(&mut self, event: Event<()>, control_flow: &mut ControlFlow) {
         match event {
             Event::WindowEvent {
@@ -48,11 +75,9 @@ impl Window {
             } => self.renderer.redraw(),
             Event::WindowEvent {event, ..} => {
                 match event {
-                    WindowEvent::MouseWheel{delta, ..} => {
-                        // handle zoom
-                        if let MouseScrollDelta::LineDelta(_, val) = delta {
-                            self.renderer.world_data.adjust_zoom(val as i32);
-                        }
+                    // handle zoom
+                    WindowEvent::MouseWheel{delta: MouseScrollDelta::LineDelta(_, val), ..} => {
+                        self.renderer.world_data.adjust_zoom(val as i32);
                     },
                     WindowEvent::Resized(new_size) => self.renderer.update_aspect_ratio(new_size),
                     WindowEvent::DroppedFile(file_path) => {
@@ -64,6 +89,45 @@ impl Window {
                             'p' | 'P' => self.renderer.world_data.toggle_paused(),
                             'f' | 'F' => self.renderer.world_data.toggle_ao(),
                             'g' | 'G' => self.renderer.world_data.toggle_grid(),
+                            's' | 'S' => self.save_output(),
+                            't' | 'T' => self.bake_texture(),
+                            'm' | 'M' => {
+                                self.compute_data.target = self.compute_data.target.next();
+                                println!("bake target set to {:?}", self.compute_data.target);
+                            },
+                            'k' | 'K' => {
+                                self.compute_data.mode = self.compute_data.mode.next();
+                                println!("bake mode set to {:?}", self.compute_data.mode);
+                            },
+                            'b' | 'B' => {
+                                self.compute_data.bent_normals = !self.compute_data.bent_normals;
+                                println!("bent normals {}", if self.compute_data.bent_normals { "enabled" } else { "disabled" });
+                            },
+                            'o' | 'O' => {
+                                self.compute_data.ground = match self.compute_data.ground {
+                                    Some(_) => None,
+                                    None => Some(GroundPlane::default())
+                                };
+                                println!("ground occluder {}", if self.compute_data.ground.is_some() { "enabled" } else { "disabled" });
+                            },
+                            'i' | 'I' => {
+                                self.image_format = self.image_format.next();
+                                println!("image format set to {:?}", self.image_format);
+                            },
+                            'c' | 'C' => match self.packing.take() {
+                                // cycling past the last channel turns packing off
+                                Some(Packing { channel: Channel::A, .. }) => println!("packing disabled"),
+                                Some(mut packing) => {
+                                    packing.channel = packing.channel.next();
+                                    println!("packing into {:?} channel of {:?}", packing.channel, packing.target.file_name());
+                                    self.packing = Some(packing);
+                                },
+                                None => {}
+                            },
+                            'e' | 'E' => {
+                                self.export_format = self.export_format.next();
+                                println!("export format set to {:?}", self.export_format);
+                            },
                             _ => {}
                         }
                     },
@@ -71,7 +135,7 @@ impl Window {
                         if let Some(key) = input.virtual_keycode {
                             if let ElementState::Pressed = input.state {
                                 if let glium::glutin::event::VirtualKeyCode::Escape = key {
-                                    self.bake_stopper.store(true, Ordering::SeqCst)
+                                    self.bake_cancel.cancel()
                                 }
                             }
                         }
@@ -118,20 +182,142 @@ impl Window {
 
     fn file_dropped(&mut self, file_path: PathBuf) {
         let ext = file_path.extension().unwrap_or_default();
-        if ext != "obj" {
+        if ext == "png" {
+            // dropped image becomes target texture bakes are packed into
+            let channel = self.packing.as_ref().map(|packing| packing.channel).unwrap_or_default();
+            println!("packing into {:?} channel of {:?}", channel, file_path.file_name());
+            self.packing = Some(Packing { target: file_path, channel });
+            return;
+        }
+        if !self.loaders.supports(&file_path) {
+            show_error(&self.renderer.message, format!("unsupported file {:?}", file_path.file_name().unwrap_or_default()));
             return;
         }
         println!("opening {:?}", file_path.file_name());
-        let name = format!("{} <{}>", APP_NAME, file_path.file_name().unwrap().to_str().unwrap());
+        let name = format!("{} <{}>", APP_NAME, file_path.file_name().unwrap_or_default().to_string_lossy());
         self.renderer.set_window_title(&name);
         if self.bake_in_progress.load(Ordering::SeqCst) {
             return;
         }
+        let mesh = match self.loaders.load(&file_path).and_then(|mesh| check_mesh(&mesh.verts, &mesh.indices).map(|_| mesh)) {
+            Ok(mesh) => mesh,
+            Err(e) => {
+                show_error(&self.renderer.message, format!("failed to open {:?}: {}", file_path.file_name().unwrap_or_default(), e));
+                return;
+            }
+        };
+        *self.renderer.message.lock().unwrap() = None;
+        for warning in &mesh.warnings {
+            println!("warning: {}", warning);
+        }
+        let is_split = self.compute_data.target != BakeTarget::Vertex;
+        let mesh = if is_split { mesh.split_corners() } else { mesh };
         self.bake_in_progress.store(true, Ordering::SeqCst);
-        let obj = read_obj(file_path);
-        let (verts, indices) = generate_vector_buffer(&obj);
-        self.renderer.update_mesh_data(verts.to_owned(), indices);
+        let (verts, indices) = (mesh.verts, mesh.indices);
+        self.renderer.update_mesh_data(verts.to_owned(), indices.to_owned());
         self.renderer.request_redraw();
-        compute_ao(Arc::clone(&self.renderer.mesh_vdata), obj, verts, Arc::clone(&self.bake_in_progress), &self.compute_data, Arc::clone(&self.bake_stopper));
+
+        let output_path = output_path(&file_path, self.compute_data.mode, self.export_format);
+        let format = self.export_format;
+        let finish_indices = indices.to_owned();
+        let finish_objects = mesh.objects.to_owned();
+        let input_path = if is_split { None } else { Some(file_path.to_owned()) };
+        let message = Arc::clone(&self.renderer.message);
+        let on_finish = move |verts: &[Vertex]| save_mesh(&output_path, format, input_path.as_deref(), verts, (&finish_indices, &finish_objects), &message);
+        let error_message = Arc::clone(&self.renderer.message);
+        let on_error = move |e| show_error(&error_message, format!("bake failed: {}", e));
+
+        self.bake_cancel = CancelToken::new();
+        self.opened_file = Some(file_path);
+        self.mesh_indices = indices.to_owned();
+        self.mesh_has_uvs = mesh.has_uvs;
+        self.mesh_objects = mesh.objects;
+        self.mesh_is_split = is_split;
+        compute_ao(Arc::clone(&self.renderer.mesh_vdata), (verts, indices), Arc::clone(&self.bake_in_progress), &self.compute_data, self.bake_cancel.clone(), on_finish, on_error);
+    }
+
+    fn save_output(&self) {
+        let file_path = match &self.opened_file {
+            Some(path) => path,
+            None => return
+        };
+        let verts = self.renderer.mesh_vdata.lock().unwrap().data.to_owned();
+        let input = if self.mesh_is_split { None } else { Some(file_path.as_path()) };
+        save_mesh(&output_path(file_path, self.compute_data.mode, self.export_format), self.export_format, input, &verts, (&self.mesh_indices, &self.mesh_objects), &self.renderer.message);
+    }
+
+    /// Bakes opened mesh into texture saved as `<name>_<mode>.<ext>`, or `<name>_<mode>_packed.png` when packing.
+    /// Bent normals are saved as `<name>_bent.png`.
+    fn bake_texture(&mut self) {
+        let file_path = match &self.opened_file {
+            Some(path) => path,
+            None => return
+        };
+        if !self.mesh_has_uvs {
+            show_error(&self.renderer.message, format!("{:?} has no texture coordinates", file_path.file_name().unwrap_or_default()));
+            return;
+        }
+        if self.bake_in_progress.load(Ordering::SeqCst) {
+            return;
+        }
+        self.bake_in_progress.store(true, Ordering::SeqCst);
+
+        let stem = file_path.file_stem().unwrap_or_default().to_string_lossy();
+        let format = self.image_format;
+        let packing = self.packing.to_owned();
+        let mode = self.compute_data.mode.name();
+        let texture_path = match packing {
+            Some(_) => file_path.with_file_name(format!("{}_{}_packed.png", stem, mode)),
+            None => file_path.with_file_name(format!("{}_{}.{}", stem, mode, format.extension()))
+        };
+        let bent_path = file_path.with_file_name(format!("{}_bent.png", stem));
+        let message = Arc::clone(&self.renderer.message);
+        let on_finish = move |texture: &Texture| {
+            let result = match &packing {
+                Some(packing) => pack_texture(&texture_path, packing, texture),
+                None => save_texture(&texture_path, format, texture)
+            };
+            match result {
+                Ok(()) => println!("saved {:?}", texture_path),
+                Err(e) => show_error(&message, format!("failed to save {:?}: {}", texture_path, e))
+            }
+            if let Some(bent_normals) = &texture.bent_normals {
+                match save_normal_map(&bent_path, texture.width, texture.height, bent_normals) {
+                    Ok(()) => println!("saved {:?}", bent_path),
+                    Err(e) => show_error(&message, format!("failed to save {:?}: {}", bent_path, e))
+                }
+            }
+        };
+
+        let error_message = Arc::clone(&self.renderer.message);
+        let on_error = move |e| show_error(&error_message, format!("bake failed: {}", e));
+
+        let verts = self.renderer.mesh_vdata.lock().unwrap().data.to_owned();
+        self.bake_cancel = CancelToken::new();
+        compute_texture((verts, self.mesh_indices.to_owned()), TEXTURE_SIZE, Arc::clone(&self.bake_in_progress), &self.compute_data, self.bake_cancel.clone(), on_finish, on_error);
+    }
+}
+
+/// Output is saved next to input file as `<name>_<mode>.<ext>`
+fn output_path(file_path: &Path, mode: BakeMode, format: ExportFormat) -> PathBuf {
+    let stem = file_path.file_stem().unwrap_or_default().to_string_lossy();
+    file_path.with_file_name(format!("{}_{}.{}", stem, mode.name(), format.extension()))
+}
+
+/// `input` is the file baked mesh was loaded from, `None` when its vertices were rearranged since
+fn save_mesh(path: &Path, format: ExportFormat, input: Option<&Path>, verts: &[Vertex], (indices, objects): (&[u32], &[MeshObject]), message: &Mutex<Option<String>>) {
+    let result = match input {
+        Some(input) => export_baked(path, format, input, verts, indices, objects),
+        None => export_mesh(path, format, verts, indices, objects)
+    };
+    match result {
+        Ok(()) => println!("saved {:?}", path),
+        Err(e) => show_error(message, format!("failed to save {:?}: {}", path, e))
     }
 }
+
+/// Prints error and shows it on top of the window until next file is opened
+fn show_error(message: &Mutex<Option<String>>, text: String) {
+    println!("{}", text);
+    *message.lock().unwrap() = Some(text);
+}
@@ -28,7 +28,7 @@ impl WorldData {
     pub fn rotate_manual(&mut self, (delta_x, delta_y): (f64, f64)) {
         self.circle += -(delta_x as f32) / 50.0;
         self.tilt += (delta_y as f32) / 60.0;
-        self.tilt = self.tilt.max(-0.999).min(0.999);
+        self.tilt = self.tilt.clamp(-0.999, 0.999);
         self.compute_eye();
         self.world_mat = Matrix4::look_at(self.eye, self.center, UP_VECTOR);
     }